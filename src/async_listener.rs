@@ -0,0 +1,493 @@
+use crate::control::InputResponder;
+use crate::ipc::{
+    ErrorMessage, HelloMessage, Message, NeedsInputMessage, OutputMessage, ProgressMessage,
+    ResponseMessage, ResultMessage, RetryMessage,
+};
+use crate::listener::{MessageListener, OutputStream};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::JoinHandle;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tokio::sync::Notify;
+use tracing::{debug, error, info, warn};
+
+/// 队列默认容量
+const DEFAULT_CAPACITY: usize = 256;
+
+/// `MessageListener` 的异步版本，回调可以执行数据库写入、HTTP 请求等耗时操作
+///
+/// 通过 `AsyncListenerAdapter` 交给执行器，回调在独立线程上依次执行，不会阻塞子进程输出的读取
+pub trait AsyncMessageListener: Send + 'static {
+    fn on_progress(&mut self, progress: ProgressMessage) -> impl Future<Output = ()> + Send;
+    fn on_error(&mut self, error: ErrorMessage) -> impl Future<Output = ()> + Send;
+    fn on_result(&mut self, result: ResultMessage) -> impl Future<Output = ()> + Send;
+    fn on_output(&mut self, output: OutputMessage) -> impl Future<Output = ()> + Send {
+        async move {
+            info!(
+                "任务输出: {}, artifacts: {:?}",
+                output.value, output.artifacts
+            );
+        }
+    }
+    fn on_hello(&mut self, hello: HelloMessage) -> impl Future<Output = ()> + Send {
+        async move {
+            info!(
+                "子进程握手: protocol_version: {}, script: {:?}",
+                hello.protocol_version, hello.script
+            );
+        }
+    }
+    fn on_response(&mut self, response: ResponseMessage) -> impl Future<Output = ()> + Send {
+        async move {
+            debug!("收到应答: id: {}, error: {:?}", response.id, response.error);
+        }
+    }
+    fn on_retry(&mut self, retry: RetryMessage) -> impl Future<Output = ()> + Send {
+        async move {
+            warn!(
+                "任务将在{}ms后重试: 第{}/{}次, 错误码: {}, {}",
+                retry.delay_ms,
+                retry.attempt,
+                retry.max_attempts,
+                retry.error_code,
+                retry.error_message
+            );
+        }
+    }
+    /// 默认放弃输入，脚本会随之取消任务
+    fn on_needs_input(
+        &mut self,
+        request: NeedsInputMessage,
+        responder: InputResponder,
+    ) -> impl Future<Output = ()> + Send {
+        async move {
+            warn!(
+                "监听器未处理输入请求，放弃输入: kind: {:?}, prompt: {}",
+                request.kind, request.prompt
+            );
+            responder.decline();
+        }
+    }
+    fn on_text(&mut self, line: String, stream: OutputStream) -> impl Future<Output = ()> + Send {
+        async move {
+            match stream {
+                OutputStream::Stdout => info!("[{stream}] {line}"),
+                OutputStream::Stderr => warn!("[{stream}] {line}"),
+            }
+        }
+    }
+    fn on_parse_error(
+        &mut self,
+        line: String,
+        error: serde_json::Error,
+    ) -> impl Future<Output = ()> + Send {
+        async move {
+            warn!("消息解析失败: {error}, line: {line}");
+        }
+    }
+    fn on_complete(&mut self, _error_code: i32) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+}
+
+/// 队列已满时的处理策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backpressure {
+    /// 阻塞读取子进程输出，直到队列有空位
+    #[default]
+    Block,
+    /// 丢弃队列中最旧的进度消息；队列中没有进度消息时阻塞
+    DropOldestProgress,
+    /// 队尾尚未处理的进度消息直接被新的进度替换；队尾不是进度时阻塞
+    Coalesce,
+}
+
+enum Event {
+    Message(Message),
+    NeedsInput(NeedsInputMessage, InputResponder),
+    Text(String, OutputStream),
+    ParseError(String, serde_json::Error),
+    Complete(i32),
+}
+
+impl Event {
+    fn is_progress(&self) -> bool {
+        matches!(self, Self::Message(Message::Progress(_)))
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    events: VecDeque<Event>,
+    closed: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    queue: Mutex<Queue>,
+    /// 队列出现空位时唤醒阻塞的生产者
+    not_full: Condvar,
+    /// 有新事件或队列关闭时唤醒消费者
+    not_empty: Notify,
+    dropped_progress: AtomicU64,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    async fn pop(&self) -> Option<Event> {
+        loop {
+            let notified = self.not_empty.notified();
+            {
+                let mut queue = self.lock();
+                if let Some(event) = queue.events.pop_front() {
+                    self.not_full.notify_all();
+                    return Some(event);
+                }
+                if queue.closed {
+                    return None;
+                }
+            }
+            notified.await;
+        }
+    }
+}
+
+/// 将 `AsyncMessageListener` 适配为 `MessageListener`
+///
+/// 消息先放入有界队列，由独立线程上的运行时依次调用异步回调；队列满时按 `Backpressure` 处理。
+/// 释放适配器时不等待，队列中剩余的消息由独立线程继续处理；需要等待处理完毕时调用 `close`
+pub struct AsyncListenerAdapter<A: AsyncMessageListener> {
+    listener: Option<A>,
+    capacity: usize,
+    backpressure: Backpressure,
+    shared: Arc<Shared>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<A: AsyncMessageListener> AsyncListenerAdapter<A> {
+    pub fn new(listener: A) -> Self {
+        Self {
+            listener: Some(listener),
+            capacity: DEFAULT_CAPACITY,
+            backpressure: Backpressure::default(),
+            shared: Arc::new(Shared::default()),
+            worker: None,
+        }
+    }
+
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    pub fn with_backpressure(mut self, backpressure: Backpressure) -> Self {
+        self.backpressure = backpressure;
+        self
+    }
+
+    /// 因队列已满被丢弃或合并的进度消息数量
+    pub fn dropped_progress(&self) -> u64 {
+        self.shared.dropped_progress.load(Ordering::Relaxed)
+    }
+
+    /// 关闭队列并阻塞等待剩余消息处理完毕，不要在异步运行时的工作线程上调用
+    pub fn close(mut self) {
+        if let Some(worker) = self.shutdown()
+            && worker.join().is_err()
+        {
+            error!("异步监听线程异常退出");
+        }
+    }
+
+    /// 关闭队列，消费线程处理完剩余消息后退出
+    fn shutdown(&mut self) -> Option<JoinHandle<()>> {
+        self.shared.lock().closed = true;
+        self.shared.not_empty.notify_one();
+        self.worker.take()
+    }
+
+    /// 首个事件到来时才启动消费线程，以便 `with_*` 配置生效
+    fn start(&mut self) {
+        let Some(mut listener) = self.listener.take() else {
+            return;
+        };
+        let shared = self.shared.clone();
+        let worker = std::thread::Builder::new()
+            .name("pyrunner-async-listener".into())
+            .spawn(move || {
+                let runtime = match tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                {
+                    Ok(runtime) => runtime,
+                    Err(e) => {
+                        error!("创建异步监听运行时失败: {e}");
+                        return;
+                    }
+                };
+                runtime.block_on(async move {
+                    while let Some(event) = shared.pop().await {
+                        deliver(&mut listener, event).await;
+                    }
+                });
+            });
+        match worker {
+            Ok(worker) => self.worker = Some(worker),
+            Err(e) => error!("创建异步监听线程失败: {e}"),
+        }
+    }
+
+    fn push(&mut self, event: Event) {
+        self.start();
+        if self.worker.is_none() {
+            warn!("异步监听线程未运行，丢弃消息");
+            return;
+        }
+
+        let mut queue = self.shared.lock();
+        let full = queue.events.len() >= self.capacity;
+        if full
+            && self.backpressure == Backpressure::Coalesce
+            && event.is_progress()
+            && let Some(back) = queue.events.back_mut().filter(|back| back.is_progress())
+        {
+            *back = event;
+            self.shared.dropped_progress.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        if full
+            && self.backpressure == Backpressure::DropOldestProgress
+            && let Some(index) = queue.events.iter().position(Event::is_progress)
+        {
+            queue.events.remove(index);
+            self.shared.dropped_progress.fetch_add(1, Ordering::Relaxed);
+        }
+        if queue.events.len() >= self.capacity {
+            queue = self.wait_not_full(queue);
+        }
+        queue.events.push_back(event);
+        drop(queue);
+        self.shared.not_empty.notify_one();
+    }
+
+    /// 阻塞等待队列出现空位，多线程运行时中通过 `block_in_place` 避免占用工作线程
+    fn wait_not_full<'a>(&'a self, queue: MutexGuard<'a, Queue>) -> MutexGuard<'a, Queue> {
+        let wait = || {
+            self.shared
+                .not_full
+                .wait_while(queue, |queue| queue.events.len() >= self.capacity)
+                .unwrap_or_else(|e| e.into_inner())
+        };
+        match Handle::try_current() {
+            Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+                tokio::task::block_in_place(wait)
+            }
+            _ => wait(),
+        }
+    }
+}
+
+async fn deliver<A: AsyncMessageListener>(listener: &mut A, event: Event) {
+    match event {
+        Event::Message(Message::Progress(progress)) => listener.on_progress(progress).await,
+        Event::Message(Message::Error(error)) => listener.on_error(error).await,
+        Event::Message(Message::Result(result)) => listener.on_result(result).await,
+        Event::Message(Message::Output(output)) => listener.on_output(output).await,
+        Event::Message(Message::Hello(hello)) => listener.on_hello(hello).await,
+        Event::Message(Message::Response(response)) => listener.on_response(response).await,
+        Event::Message(Message::NeedsInput(request)) => {
+            let responder = InputResponder::new(request.id, None);
+            listener.on_needs_input(request, responder).await
+        }
+        Event::Message(Message::Retry(retry)) => listener.on_retry(retry).await,
+        Event::NeedsInput(request, responder) => listener.on_needs_input(request, responder).await,
+        Event::Text(line, stream) => listener.on_text(line, stream).await,
+        Event::ParseError(line, error) => listener.on_parse_error(line, error).await,
+        Event::Complete(error_code) => listener.on_complete(error_code).await,
+    }
+}
+
+impl<A: AsyncMessageListener> MessageListener for AsyncListenerAdapter<A> {
+    fn dispatch(&mut self, message: Message) {
+        self.push(Event::Message(message));
+    }
+    fn on_progress(&mut self, progress: ProgressMessage) {
+        self.push(Event::Message(Message::Progress(progress)));
+    }
+    fn on_error(&mut self, error: ErrorMessage) {
+        self.push(Event::Message(Message::Error(error)));
+    }
+    fn on_result(&mut self, result: ResultMessage) {
+        self.push(Event::Message(Message::Result(result)));
+    }
+    fn on_output(&mut self, output: OutputMessage) {
+        self.push(Event::Message(Message::Output(output)));
+    }
+    fn on_hello(&mut self, hello: HelloMessage) {
+        self.push(Event::Message(Message::Hello(hello)));
+    }
+    fn on_response(&mut self, response: ResponseMessage) {
+        self.push(Event::Message(Message::Response(response)));
+    }
+    fn on_retry(&mut self, retry: RetryMessage) {
+        self.push(Event::Message(Message::Retry(retry)));
+    }
+    fn on_needs_input(&mut self, request: NeedsInputMessage, responder: InputResponder) {
+        self.push(Event::NeedsInput(request, responder));
+    }
+    fn on_text(&mut self, line: String, stream: OutputStream) {
+        self.push(Event::Text(line, stream));
+    }
+    fn on_parse_error(&mut self, line: String, error: serde_json::Error) {
+        self.push(Event::ParseError(line, error));
+    }
+    fn on_complete(&mut self, error_code: i32) {
+        self.push(Event::Complete(error_code));
+    }
+}
+
+impl<A: AsyncMessageListener> Drop for AsyncListenerAdapter<A> {
+    /// 只关闭队列不等待，在运行时的工作线程上释放也不会被监听器的回调阻塞
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[derive(Default, Clone)]
+    struct SlowListener {
+        progress: Arc<Mutex<Vec<u64>>>,
+        results: Arc<Mutex<Vec<ResultMessage>>>,
+        delay: Duration,
+    }
+
+    impl AsyncMessageListener for SlowListener {
+        async fn on_progress(&mut self, progress: ProgressMessage) {
+            tokio::time::sleep(self.delay).await;
+            self.progress.lock().unwrap().push(progress.done);
+        }
+        async fn on_error(&mut self, _error: ErrorMessage) {}
+        async fn on_result(&mut self, result: ResultMessage) {
+            self.results.lock().unwrap().push(result);
+        }
+    }
+
+    fn run(backpressure: Backpressure, delay: Duration) -> (SlowListener, u64) {
+        let listener = SlowListener {
+            delay,
+            ..Default::default()
+        };
+        let mut adapter = AsyncListenerAdapter::new(listener.clone())
+            .with_capacity(4)
+            .with_backpressure(backpressure);
+        for done in 1..=50 {
+            adapter.on_progress(ProgressMessage::new(done, 50));
+        }
+        adapter.on_result(ResultMessage::new(1, 2));
+        let dropped = adapter.dropped_progress();
+        adapter.close();
+        (listener, dropped)
+    }
+
+    #[test]
+    fn test_block() {
+        let (listener, dropped) = run(Backpressure::Block, Duration::from_millis(1));
+        assert_eq!(dropped, 0);
+        assert_eq!(
+            *listener.progress.lock().unwrap(),
+            (1..=50).collect::<Vec<_>>()
+        );
+        assert_eq!(listener.results.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_drop_oldest_progress() {
+        let (listener, dropped) = run(Backpressure::DropOldestProgress, Duration::from_millis(20));
+        let progress = listener.progress.lock().unwrap();
+        assert!(dropped > 0);
+        assert_eq!(progress.len() as u64 + dropped, 50);
+        assert_eq!(progress.last(), Some(&50));
+        assert_eq!(listener.results.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_coalesce() {
+        let (listener, dropped) = run(Backpressure::Coalesce, Duration::from_millis(20));
+        let progress = listener.progress.lock().unwrap();
+        assert!(dropped > 0);
+        assert!(progress.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(progress.last(), Some(&50));
+        assert_eq!(listener.results.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_coalesce_only_when_full() {
+        let listener = SlowListener {
+            delay: Duration::from_millis(5),
+            ..Default::default()
+        };
+        let mut adapter = AsyncListenerAdapter::new(listener.clone())
+            .with_capacity(64)
+            .with_backpressure(Backpressure::Coalesce);
+        for done in 1..=50 {
+            adapter.on_progress(ProgressMessage::new(done, 50));
+        }
+        assert_eq!(adapter.dropped_progress(), 0);
+        adapter.close();
+        assert_eq!(
+            *listener.progress.lock().unwrap(),
+            (1..=50).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_drop_does_not_wait() {
+        let listener = SlowListener {
+            delay: Duration::from_secs(1),
+            ..Default::default()
+        };
+        let mut adapter = AsyncListenerAdapter::new(listener.clone());
+        adapter.on_progress(ProgressMessage::new(1, 1));
+
+        let start = std::time::Instant::now();
+        drop(adapter);
+        assert!(start.elapsed() < Duration::from_millis(500));
+
+        // 剩余的消息仍由独立线程处理完
+        for _ in 0..30 {
+            if !listener.progress.lock().unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(*listener.progress.lock().unwrap(), vec![1]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_with_executor() {
+        let listener = SlowListener {
+            delay: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let mut adapter = AsyncListenerAdapter::new(listener.clone()).with_capacity(2);
+        let executor = crate::executor::TaskExecutor::new(
+            "python".into(),
+            vec!["src/demo_progress.py".into()],
+        );
+        executor.execute(&mut adapter).await.unwrap();
+        tokio::task::spawn_blocking(move || adapter.close())
+            .await
+            .unwrap();
+        assert_eq!(listener.progress.lock().unwrap().len(), 10);
+        assert_eq!(listener.results.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,406 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use crate::control::InputResponder;
+use crate::ipc::{
+    ErrorMessage, HelloMessage, Message, NeedsInputMessage, OutputMessage, ProgressMessage,
+    ResponseMessage, ResultMessage, RetryMessage,
+};
+use crate::listener::{MessageListener, OutputStream};
+
+/// 把每条消息广播给多个监听器
+///
+/// 组合子本身都实现了 `MessageListener`，组合好之后既可以交给执行器，
+/// 也可以包一层 `Arc<Mutex<_>>` 交给 `create_message_channel`。被广播的监听器需要是 `Send`，
+/// 这样组合出的 `Fanout` 仍然可以提交给 `TaskPool`/`TaskRegistry`
+///
+/// 输入请求只有一个应答者：第一个监听器拿到真正的 `InputResponder`，
+/// 其余监听器收到的 responder 不会送达子进程
+#[derive(Default)]
+pub struct Fanout {
+    listeners: Vec<Box<dyn MessageListener + Send>>,
+}
+
+impl Fanout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with<L: MessageListener + Send + 'static>(mut self, listener: L) -> Self {
+        self.push(listener);
+        self
+    }
+
+    pub fn push<L: MessageListener + Send + 'static>(&mut self, listener: L) {
+        self.listeners.push(Box::new(listener));
+    }
+
+    pub fn len(&self) -> usize {
+        self.listeners.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.listeners.is_empty()
+    }
+
+    fn broadcast<T: Clone>(&mut self, value: T, f: impl Fn(&mut dyn MessageListener, T)) {
+        if let Some((last, rest)) = self.listeners.split_last_mut() {
+            for listener in rest {
+                f(listener.as_mut(), value.clone());
+            }
+            f(last.as_mut(), value);
+        }
+    }
+}
+
+impl MessageListener for Fanout {
+    fn on_progress(&mut self, progress: ProgressMessage) {
+        self.broadcast(progress, |l, p| l.on_progress(p));
+    }
+    fn on_error(&mut self, error: ErrorMessage) {
+        self.broadcast(error, |l, e| l.on_error(e));
+    }
+    fn on_result(&mut self, result: ResultMessage) {
+        self.broadcast(result, |l, r| l.on_result(r));
+    }
+    fn on_output(&mut self, output: OutputMessage) {
+        self.broadcast(output, |l, o| l.on_output(o));
+    }
+    fn on_hello(&mut self, hello: HelloMessage) {
+        self.broadcast(hello, |l, h| l.on_hello(h));
+    }
+    fn on_response(&mut self, response: ResponseMessage) {
+        self.broadcast(response, |l, r| l.on_response(r));
+    }
+    fn on_retry(&mut self, retry: RetryMessage) {
+        self.broadcast(retry, |l, r| l.on_retry(r));
+    }
+    fn on_needs_input(&mut self, request: NeedsInputMessage, responder: InputResponder) {
+        let Some((first, rest)) = self.listeners.split_first_mut() else {
+            responder.decline();
+            return;
+        };
+        for listener in rest {
+            listener.on_needs_input(request.clone(), InputResponder::new(request.id, None));
+        }
+        first.on_needs_input(request, responder);
+    }
+    fn on_stderr(&mut self, line: String) {
+        self.broadcast(line, |l, line| l.on_stderr(line));
+    }
+    fn on_text(&mut self, line: String, stream: OutputStream) {
+        self.broadcast(line, |l, line| l.on_text(line, stream));
+    }
+    fn on_parse_error(&mut self, line: String, error: serde_json::Error) {
+        let Some((last, rest)) = self.listeners.split_last_mut() else {
+            return;
+        };
+        // serde_json::Error 不能 clone，按原错误的描述为其他监听器各构造一个。
+        // 行上可能带有分帧前缀，不能重新解析整行
+        for listener in rest {
+            let error = <serde_json::Error as serde::de::Error>::custom(error.to_string());
+            listener.on_parse_error(line.clone(), error);
+        }
+        last.on_parse_error(line, error);
+    }
+    fn on_complete(&mut self, error_code: i32) {
+        self.broadcast(error_code, |l, code| l.on_complete(code));
+    }
+}
+
+/// 对每条协议消息做变换后交给内层监听器，返回 `None` 表示丢弃
+///
+/// 普通文本行、解析失败和任务结束不经过变换。输入请求被丢弃或变换成其他类型时自动放弃输入
+pub struct Map<L, F> {
+    inner: L,
+    f: F,
+}
+
+impl<L, F> Map<L, F>
+where
+    L: MessageListener,
+    F: FnMut(Message) -> Option<Message> + Send,
+{
+    pub fn new(inner: L, f: F) -> Self {
+        Self { inner, f }
+    }
+
+    pub fn inner(&self) -> &L {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut L {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+
+    fn forward(&mut self, message: Message, responder: Option<InputResponder>) {
+        match ((self.f)(message), responder) {
+            (Some(Message::NeedsInput(request)), Some(responder)) => {
+                self.inner.on_needs_input(request, responder)
+            }
+            (mapped, responder) => {
+                if let Some(responder) = responder {
+                    responder.decline();
+                }
+                if let Some(message) = mapped {
+                    self.inner.dispatch(message);
+                }
+            }
+        }
+    }
+}
+
+impl<L, F> MessageListener for Map<L, F>
+where
+    L: MessageListener,
+    F: FnMut(Message) -> Option<Message> + Send,
+{
+    fn on_progress(&mut self, progress: ProgressMessage) {
+        self.forward(Message::Progress(progress), None);
+    }
+    fn on_error(&mut self, error: ErrorMessage) {
+        self.forward(Message::Error(error), None);
+    }
+    fn on_result(&mut self, result: ResultMessage) {
+        self.forward(Message::Result(result), None);
+    }
+    fn on_output(&mut self, output: OutputMessage) {
+        self.forward(Message::Output(output), None);
+    }
+    fn on_hello(&mut self, hello: HelloMessage) {
+        self.forward(Message::Hello(hello), None);
+    }
+    fn on_response(&mut self, response: ResponseMessage) {
+        self.forward(Message::Response(response), None);
+    }
+    fn on_retry(&mut self, retry: RetryMessage) {
+        self.forward(Message::Retry(retry), None);
+    }
+    fn on_needs_input(&mut self, request: NeedsInputMessage, responder: InputResponder) {
+        self.forward(Message::NeedsInput(request), Some(responder));
+    }
+    fn on_stderr(&mut self, line: String) {
+        self.inner.on_stderr(line);
+    }
+    fn on_text(&mut self, line: String, stream: OutputStream) {
+        self.inner.on_text(line, stream);
+    }
+    fn on_parse_error(&mut self, line: String, error: serde_json::Error) {
+        self.inner.on_parse_error(line, error);
+    }
+    fn on_complete(&mut self, error_code: i32) {
+        self.inner.on_complete(error_code);
+    }
+}
+
+/// 只把满足条件的协议消息交给内层监听器
+pub type Filter<L> = Map<L, Box<dyn FnMut(Message) -> Option<Message> + Send>>;
+
+impl<L: MessageListener> Filter<L> {
+    pub fn filter<P>(inner: L, mut predicate: P) -> Self
+    where
+        P: FnMut(&Message) -> bool + Send + 'static,
+    {
+        Map::new(
+            inner,
+            Box::new(move |message: Message| predicate(&message).then_some(message)),
+        )
+    }
+
+    /// 只转发 `kinds` 中列出的消息类型，类型名称见 `Message::KINDS`
+    pub fn only(inner: L, kinds: &[&str]) -> Self {
+        let kinds: Vec<String> = kinds.iter().map(|kind| kind.to_string()).collect();
+        Self::filter(inner, move |message| {
+            kinds.iter().any(|kind| kind == message.kind())
+        })
+    }
+
+    /// 转发除 `kinds` 以外的消息类型
+    pub fn except(inner: L, kinds: &[&str]) -> Self {
+        let kinds: Vec<String> = kinds.iter().map(|kind| kind.to_string()).collect();
+        Self::filter(inner, move |message| {
+            !kinds.iter().any(|kind| kind == message.kind())
+        })
+    }
+}
+
+/// 记录经过的协议消息后原样转发，用于调试、测试或任务结束后生成报告
+pub struct Tee<L> {
+    inner: L,
+    recording: Arc<Mutex<VecDeque<Message>>>,
+    limit: Option<usize>,
+}
+
+impl<L: MessageListener> Tee<L> {
+    pub fn new(inner: L) -> Self {
+        Self {
+            inner,
+            recording: Arc::default(),
+            limit: None,
+        }
+    }
+
+    /// 最多保留最近的 `limit` 条消息
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// 记录的消息，监听器交给执行器之后仍然可以读取
+    pub fn recording(&self) -> Arc<Mutex<VecDeque<Message>>> {
+        self.recording.clone()
+    }
+
+    pub fn inner(&self) -> &L {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut L {
+        &mut self.inner
+    }
+
+    fn record(&self, message: Message) {
+        let mut recording = self.recording.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(limit) = self.limit {
+            while recording.len() >= limit.max(1) {
+                recording.pop_front();
+            }
+        }
+        recording.push_back(message);
+    }
+}
+
+impl<L: MessageListener> MessageListener for Tee<L> {
+    fn on_progress(&mut self, progress: ProgressMessage) {
+        self.record(Message::Progress(progress.clone()));
+        self.inner.on_progress(progress);
+    }
+    fn on_error(&mut self, error: ErrorMessage) {
+        self.record(Message::Error(error.clone()));
+        self.inner.on_error(error);
+    }
+    fn on_result(&mut self, result: ResultMessage) {
+        self.record(Message::Result(result));
+        self.inner.on_result(result);
+    }
+    fn on_output(&mut self, output: OutputMessage) {
+        self.record(Message::Output(output.clone()));
+        self.inner.on_output(output);
+    }
+    fn on_hello(&mut self, hello: HelloMessage) {
+        self.record(Message::Hello(hello.clone()));
+        self.inner.on_hello(hello);
+    }
+    fn on_response(&mut self, response: ResponseMessage) {
+        self.record(Message::Response(response.clone()));
+        self.inner.on_response(response);
+    }
+    fn on_retry(&mut self, retry: RetryMessage) {
+        self.record(Message::Retry(retry.clone()));
+        self.inner.on_retry(retry);
+    }
+    fn on_needs_input(&mut self, request: NeedsInputMessage, responder: InputResponder) {
+        self.record(Message::NeedsInput(request.clone()));
+        self.inner.on_needs_input(request, responder);
+    }
+    fn on_stderr(&mut self, line: String) {
+        self.inner.on_stderr(line);
+    }
+    fn on_text(&mut self, line: String, stream: OutputStream) {
+        self.inner.on_text(line, stream);
+    }
+    fn on_parse_error(&mut self, line: String, error: serde_json::Error) {
+        self.inner.on_parse_error(line, error);
+    }
+    fn on_complete(&mut self, error_code: i32) {
+        self.inner.on_complete(error_code);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::DEFAULT_MESSAGE_PREFIX;
+    use crate::test_util::RecordingListener;
+
+    #[test]
+    fn test_fanout() {
+        let console = RecordingListener::default();
+        let file = RecordingListener::default();
+        let mut listener = Fanout::new()
+            .with(console.clone())
+            .with(Filter::only(file.clone(), &["Error", "Result"]));
+
+        listener.dispatch(Message::Progress(ProgressMessage::new(1, 2)));
+        listener.dispatch(
+            Message::parse(r#"{"Result": {"pages": 1, "words": 2}}"#)
+                .unwrap()
+                .unwrap(),
+        );
+        let line = r#"{"Progress": oops}"#;
+        let error = Message::parse(line).unwrap_err();
+        let parse_error = format!("parse error {line}: {error}");
+        listener.on_parse_error(line.into(), error);
+        listener.on_complete(0);
+
+        assert_eq!(
+            console.events(),
+            vec!["progress 1", "result", &parse_error, "complete 0"]
+        );
+        assert_eq!(file.events(), vec!["result", &parse_error, "complete 0"]);
+    }
+
+    #[test]
+    fn test_fanout_prefixed_parse_error() {
+        let first = RecordingListener::default();
+        let second = RecordingListener::default();
+        let mut listener = Fanout::new().with(first.clone()).with(second.clone());
+
+        let payload = r#"{"Progress": oops}"#;
+        let line = format!("{DEFAULT_MESSAGE_PREFIX}{payload}");
+        let error = Message::parse(payload).unwrap_err();
+        let expected = vec![format!("parse error {line}: {error}")];
+        listener.on_parse_error(line, error);
+
+        assert_eq!(first.events(), expected);
+        assert_eq!(second.events(), expected);
+    }
+
+    #[test]
+    fn test_map_and_tee() {
+        let recording = RecordingListener::default();
+        let tee = Tee::new(recording.clone()).with_limit(2);
+        let recorded = tee.recording();
+        // 把子进程报告的错误码统一映射为 2001，并丢弃 size 为 0 的进度
+        let mut listener = Map::new(tee, |message| match message {
+            Message::Error(mut error) => {
+                error.error_code = 2001;
+                Some(Message::Error(error))
+            }
+            Message::Progress(progress) if progress.size == 0 => None,
+            message => Some(message),
+        });
+
+        listener.on_progress(ProgressMessage::new(1, 0));
+        listener.on_progress(ProgressMessage::new(1, 2));
+        listener.on_progress(ProgressMessage::new(2, 2));
+        listener.on_error(ErrorMessage::new(1, "失败".into()));
+
+        assert_eq!(
+            recording.events(),
+            vec!["progress 1", "progress 2", "error 2001"]
+        );
+        let recorded: Vec<_> = recorded.lock().unwrap().iter().cloned().collect();
+        assert_eq!(
+            recorded,
+            vec![
+                Message::Progress(ProgressMessage::new(2, 2)),
+                Message::Error(ErrorMessage::new(2001, "失败".into())),
+            ]
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use crate::error::{PyRunnerError, Result};
+use crate::ipc::{Command, InputMessage, RequestMessage, ResponseMessage};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::{Notify, oneshot};
+use tracing::warn;
+
+/// 控制通道句柄，可在任意线程克隆并向正在执行的脚本发送命令
+///
+/// 命令在任务开始前发送也不会丢失，执行器启动后按顺序写入子进程的 stdin
+#[derive(Clone, Default)]
+pub struct ControlHandle {
+    inner: Arc<ControlState>,
+}
+
+#[derive(Default)]
+struct ControlState {
+    commands: Mutex<VecDeque<Command>>,
+    notify: Notify,
+    next_request_id: AtomicU64,
+    pending: Mutex<HashMap<u64, oneshot::Sender<ResponseMessage>>>,
+}
+
+impl ControlHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn send(&self, command: Command) {
+        lock(&self.inner.commands).push_back(command);
+        self.inner.notify.notify_one();
+    }
+
+    pub fn pause(&self) {
+        self.send(Command::Pause);
+    }
+
+    pub fn resume(&self) {
+        self.send(Command::Resume);
+    }
+
+    /// 协作式取消：脚本收到后自行退出，执行器返回 `TaskCancelled`
+    ///
+    /// 脚本不响应时仍需使用 `CancelHandle` 强制终止
+    pub fn cancel(&self) {
+        self.send(Command::Cancel);
+    }
+
+    /// 应答脚本的 `NeedsInput`，`value` 为 `None` 表示放弃输入
+    pub fn provide_input(&self, id: u64, value: Option<String>) {
+        self.send(Command::Input(InputMessage { id, value }));
+    }
+
+    /// 立即发送请求，返回的 future 在脚本应答后完成
+    ///
+    /// 任务结束时仍未应答返回 `ChannelClosed`
+    pub fn request(
+        &self,
+        payload: serde_json::Value,
+    ) -> impl Future<Output = Result<ResponseMessage>> + Send + 'static {
+        let id = self.inner.next_request_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let (sender, receiver) = oneshot::channel();
+        lock(&self.inner.pending).insert(id, sender);
+        self.send(Command::Request(RequestMessage::new(id, payload)));
+        async move { receiver.await.map_err(|_| PyRunnerError::ChannelClosed) }
+    }
+
+    /// 等待下一条待发送的命令
+    pub(crate) async fn recv(&self) -> Command {
+        loop {
+            let notified = self.inner.notify.notified();
+            if let Some(command) = lock(&self.inner.commands).pop_front() {
+                return command;
+            }
+            notified.await;
+        }
+    }
+
+    /// 将应答交给等待中的 `request`，没有对应请求时返回 false
+    pub(crate) fn resolve(&self, response: &ResponseMessage) -> bool {
+        match lock(&self.inner.pending).remove(&response.id) {
+            Some(sender) => sender.send(response.clone()).is_ok(),
+            None => {
+                warn!("收到未知请求的应答: id: {}", response.id);
+                false
+            }
+        }
+    }
+
+    /// 任务结束后调用，所有未应答的请求返回 `ChannelClosed`
+    pub(crate) fn close_pending(&self) {
+        lock(&self.inner.pending).clear();
+    }
+}
+
+/// 交给监听器的输入应答器，可以保存下来在任意线程异步应答
+///
+/// 未开启控制通道或消息经过 IPC 转发时无法应答，`reply`/`decline` 返回 false
+#[derive(Debug, Clone)]
+pub struct InputResponder {
+    id: u64,
+    control: Option<ControlHandle>,
+}
+
+impl InputResponder {
+    pub(crate) fn new(id: u64, control: Option<ControlHandle>) -> Self {
+        Self { id, control }
+    }
+
+    /// 对应 `NeedsInputMessage::id`
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn reply<S: Into<String>>(&self, value: S) -> bool {
+        self.respond(Some(value.into()))
+    }
+
+    /// 放弃输入，脚本收到后取消任务
+    pub fn decline(&self) -> bool {
+        self.respond(None)
+    }
+
+    fn respond(&self, value: Option<String>) -> bool {
+        match &self.control {
+            Some(control) => {
+                control.provide_input(self.id, value);
+                true
+            }
+            None => {
+                warn!("无法应答输入请求，控制通道未开启: id: {}", self.id);
+                false
+            }
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl std::fmt::Debug for ControlHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ControlHandle")
+            .field("queued", &lock(&self.inner.commands).len())
+            .field("pending", &lock(&self.inner.pending).len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_request_response() {
+        let control = ControlHandle::new();
+        control.pause();
+        let response = control.request(serde_json::json!("ping"));
+        let unanswered = control.request(serde_json::Value::Null);
+
+        assert_eq!(control.recv().await, Command::Pause);
+        let id = match control.recv().await {
+            Command::Request(request) => request.id,
+            other => panic!("unexpected command: {other:?}"),
+        };
+        assert!(control.resolve(&ResponseMessage::new(id, serde_json::json!("pong"))));
+        assert_eq!(response.await.unwrap().payload, serde_json::json!("pong"));
+
+        control.close_pending();
+        assert!(matches!(
+            unanswered.await,
+            Err(PyRunnerError::ChannelClosed)
+        ));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::traceback::PythonFrame;
 use std::fmt;
 use thiserror::Error;
 
@@ -7,26 +8,27 @@ pub enum PyRunnerError {
     #[error("任务执行失败: {message}")]
     TaskExecutionFailed { message: String },
 
-    #[allow(dead_code)]
     #[error("任务超时: {task_id}")]
     TaskTimeout { task_id: u64 },
 
-    #[allow(dead_code)]
     #[error("任务被取消: {task_id}")]
     TaskCancelled { task_id: u64 },
 
     #[error(transparent)]
     JoinError(#[from] tokio::task::JoinError),
 
-    #[allow(dead_code)]
-    #[error("Python执行错误: {0}")]
-    PythonError(String),
+    #[error("Python执行错误: {exc_type}: {message}")]
+    PythonError {
+        exc_type: String,
+        message: String,
+        /// 调用栈，最外层在前
+        frames: Vec<PythonFrame>,
+    },
 
     #[allow(dead_code)]
     #[error("Python变量未找到: {variable}")]
     PythonVariableNotFound { variable: String },
 
-    #[allow(dead_code)]
     #[error("Python模块导入失败: {module}")]
     PythonModuleImportFailed { module: String },
 
@@ -48,6 +50,9 @@ pub enum PyRunnerError {
     #[error("权限不足: {path}")]
     PermissionDenied { path: String },
 
+    #[error("密码错误: 已尝试{attempts}次")]
+    PasswordIncorrect { attempts: u32 },
+
     #[error(transparent)]
     JsonError(#[from] serde_json::Error),
 
@@ -55,9 +60,12 @@ pub enum PyRunnerError {
     #[error("进程创建失败: {0}")]
     ProcessCreationFailed(String),
 
-    #[allow(dead_code)]
-    #[error("进程执行失败: {0:?}")]
-    ProcessExecutionFailed(std::process::ExitStatus),
+    #[error("进程执行失败: {status:?}{}", format_stderr_tail(.stderr_tail))]
+    ProcessExecutionFailed {
+        status: std::process::ExitStatus,
+        /// 子进程退出前输出到 stderr 的最后若干行
+        stderr_tail: Vec<String>,
+    },
 
     #[cfg(unix)]
     #[error(transparent)]
@@ -109,6 +117,14 @@ pub enum PyRunnerError {
 
 pub type Result<T> = std::result::Result<T, PyRunnerError>;
 
+fn format_stderr_tail(stderr_tail: &[String]) -> String {
+    if stderr_tail.is_empty() {
+        String::new()
+    } else {
+        format!("\nstderr:\n{}", stderr_tail.join("\n"))
+    }
+}
+
 impl PyRunnerError {
     #[allow(dead_code)]
     pub fn task_execution_failed<S: Into<String>>(message: S) -> Self {
@@ -117,14 +133,17 @@ impl PyRunnerError {
         }
     }
 
-    #[allow(dead_code)]
     pub fn task_timeout(task_id: u64) -> Self {
         Self::TaskTimeout { task_id }
     }
 
     #[allow(dead_code)]
-    pub fn python_error<S: Into<String>>(message: S) -> Self {
-        Self::PythonError(message.into())
+    pub fn python_error<T: Into<String>, S: Into<String>>(exc_type: T, message: S) -> Self {
+        Self::PythonError {
+            exc_type: exc_type.into(),
+            message: message.into(),
+            frames: Vec::new(),
+        }
     }
 
     #[allow(dead_code)]
@@ -188,7 +207,7 @@ impl PyRunnerError {
             Self::TaskTimeout { .. } => 1002,
             Self::TaskCancelled { .. } => 1003,
             Self::JoinError(_) => 1004,
-            Self::PythonError(_) => 2001,
+            Self::PythonError { .. } => 2001,
             Self::PythonVariableNotFound { .. } => 2002,
             Self::PythonModuleImportFailed { .. } => 2003,
             Self::JniError(_) => 3001,
@@ -196,9 +215,10 @@ impl PyRunnerError {
             Self::IoError(_) => 4001,
             Self::FileNotFound { .. } => 4002,
             Self::PermissionDenied { .. } => 4003,
+            Self::PasswordIncorrect { .. } => 4004,
             Self::JsonError(_) => 5001,
             Self::ProcessCreationFailed(_) => 6001,
-            Self::ProcessExecutionFailed(..) => 6002,
+            Self::ProcessExecutionFailed { .. } => 6002,
             #[cfg(unix)]
             Self::NixError(_) => 7001,
             Self::EnvVarError(_) => 7002,
@@ -295,6 +315,7 @@ impl std::error::Error for ContextualError {
 }
 
 #[allow(dead_code)]
+#[allow(clippy::result_large_err)]
 pub trait ResultExt<T> {
     fn with_context<F>(self, f: F) -> std::result::Result<T, ContextualError>
     where
@@ -1,33 +1,416 @@
+use crate::cancel::CancelHandle;
+use crate::control::{ControlHandle, InputResponder};
 use crate::error::{PyRunnerError, Result};
-use crate::listener::MessageListener;
-use std::process::Stdio;
-use tokio::io::{AsyncBufReadExt, BufReader};
-use tokio::process::Command;
-use tracing::{error, info, instrument};
+use crate::ipc::{
+    Command, ErrorMessage, InputKind, InputMessage, Message, PROTOCOL_VERSION, RetryMessage,
+};
+use crate::limits::ResourceLimits;
+use crate::listener::{MessageListener, OutputStream, looks_like_message};
+use crate::process::{ProcessHandle, spawn_child, wait_exited};
+use crate::python_client::install_python_client;
+use crate::retry::RetryPolicy;
+use crate::stream::{EventQueue, TaskEvent, TaskStream};
+use crate::traceback::TracebackParser;
+use nix::sys::signal::{self, Signal};
+use nix::unistd::{Pid, getpid, setsid};
+use std::collections::{HashMap, VecDeque};
+use std::ffi::OsString;
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::PathBuf;
+use std::process::{ExitStatus, Stdio};
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::unix::pipe;
+use tokio::process::{Child, ChildStdin};
+use tokio::time::Instant;
+use tracing::{Instrument, Span, error, info, info_span, instrument, warn};
 
+/// 发送 SIGTERM 后等待子进程自行退出的默认时长
+const DEFAULT_KILL_GRACE: Duration = Duration::from_secs(3);
+
+/// 分帧模式下推荐使用的消息前缀
+pub const DEFAULT_MESSAGE_PREFIX: &str = "@@PYRUNNER@@ ";
+
+/// 分帧模式下将消息前缀告知子进程的环境变量
+pub const MESSAGE_PREFIX_ENV: &str = "PYRUNNER_MESSAGE_PREFIX";
+
+/// 消息专用管道在子进程中的默认 fd
+pub const DEFAULT_MESSAGE_FD: RawFd = 3;
+
+/// 将消息专用管道的 fd 告知子进程的环境变量
+pub const MESSAGE_FD_ENV: &str = "PYRUNNER_MESSAGE_FD";
+
+/// 将 runner 支持的协议版本告知子进程的环境变量，子进程据此决定是否降级
+pub const PROTOCOL_VERSION_ENV: &str = "PYRUNNER_PROTOCOL_VERSION";
+
+/// 告知子进程 stdin 上承载控制命令的环境变量
+pub const CONTROL_ENV: &str = "PYRUNNER_CONTROL";
+
+/// 同类输入（如密码）默认最多尝试的次数
+const DEFAULT_INPUT_ATTEMPTS: u32 = 3;
+
+/// 将输入的最大尝试次数告知子进程的环境变量
+pub const INPUT_ATTEMPTS_ENV: &str = "PYRUNNER_INPUT_ATTEMPTS";
+
+/// 子进程以该错误码的 `Error` 消息报告密码错误，与 `PasswordIncorrect` 的错误码一致
+const PASSWORD_INCORRECT_CODE: i32 = 4004;
+
+/// 子进程异常退出时附带在错误中的 stderr 行数
+const DEFAULT_STDERR_TAIL_LINES: usize = 50;
+
+/// 子进程退出后继续读取剩余输出的时长，脱离进程组的孙进程可能一直持有输出管道
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// 执行过程中需要跨消息记录的协议状态
+#[derive(Debug, Default)]
+struct ProtocolState {
+    handshake_done: bool,
+    input_attempts: HashMap<InputKind, u32>,
+    password_incorrect: bool,
+}
+
+impl ProtocolState {
+    fn attempts(&self, kind: InputKind) -> u32 {
+        self.input_attempts.get(&kind).copied().unwrap_or_default()
+    }
+}
+
+/// 导致子进程被提前终止的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interrupt {
+    Cancelled,
+    Timeout,
+    IdleTimeout,
+}
+
+#[derive(Clone)]
 pub struct TaskExecutor {
     exec: String,
     argv: Vec<String>,
+    envs: Vec<(String, String)>,
+    task_id: u64,
+    cancel: CancelHandle,
+    kill_grace: Duration,
+    timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    stderr_tail_lines: usize,
+    message_prefix: Option<String>,
+    message_fd: Option<RawFd>,
+    python_client_dir: Option<PathBuf>,
+    require_hello: bool,
+    control: Option<ControlHandle>,
+    input_attempts: u32,
+    process: ProcessHandle,
+    retry: Option<RetryPolicy>,
+    limits: ResourceLimits,
 }
 
 impl TaskExecutor {
     pub fn new(exec: String, argv: Vec<String>) -> Self {
-        Self { exec, argv }
+        Self {
+            exec,
+            argv,
+            envs: Vec::new(),
+            task_id: 0,
+            cancel: CancelHandle::new(),
+            kill_grace: DEFAULT_KILL_GRACE,
+            timeout: None,
+            idle_timeout: None,
+            stderr_tail_lines: DEFAULT_STDERR_TAIL_LINES,
+            message_prefix: None,
+            message_fd: None,
+            python_client_dir: None,
+            require_hello: false,
+            control: None,
+            input_attempts: DEFAULT_INPUT_ATTEMPTS,
+            process: ProcessHandle::new(),
+            retry: None,
+            limits: ResourceLimits::default(),
+        }
+    }
+
+    pub fn with_task_id(mut self, task_id: u64) -> Self {
+        self.task_id = task_id;
+        self
+    }
+
+    /// 为子进程设置额外的环境变量，适合传递密码等不宜出现在命令行中的参数
+    pub fn with_env<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.envs.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn with_cancel_handle(mut self, cancel: CancelHandle) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    pub fn with_kill_grace(mut self, kill_grace: Duration) -> Self {
+        self.kill_grace = kill_grace;
+        self
+    }
+
+    /// 任务总执行时长上限，超时后终止子进程并返回 `TaskTimeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// stdout 上连续多久没有收到 `Message` 即视为卡死，终止子进程并返回 `TaskTimeout`
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// 保留最近多少行 stderr，用于附加到 `ProcessExecutionFailed` 错误中
+    pub fn with_stderr_tail_lines(mut self, lines: usize) -> Self {
+        self.stderr_tail_lines = lines;
+        self
+    }
+
+    /// 开启分帧模式：只有以 `prefix` 开头的 stdout 行才按协议消息解析，其余都是普通文本
+    ///
+    /// 前缀会通过环境变量 `PYRUNNER_MESSAGE_PREFIX` 传给子进程
+    pub fn with_message_prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.message_prefix = Some(prefix.into());
+        self
+    }
+
+    /// 为子进程额外打开一个专用于协议消息的管道，并映射到子进程的 `fd`
+    ///
+    /// fd 通过环境变量 `PYRUNNER_MESSAGE_FD` 传给子进程。开启后 stdout 上的内容全部视为普通文本，
+    /// 消息前缀（如有）作用于该管道
+    pub fn with_message_fd(mut self, fd: RawFd) -> Self {
+        self.message_fd = Some(fd);
+        self
+    }
+
+    /// 执行前将 Python 客户端模块 `pyrunner` 释放到 `dir`，并将其加入子进程的 `PYTHONPATH`
+    pub fn with_python_client<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.python_client_dir = Some(dir.into());
+        self
+    }
+
+    /// 要求子进程发送的第一条消息必须是 `Hello`，否则终止子进程并返回 `ConfigError`
+    ///
+    /// 默认不要求，未发送 `Hello` 的旧脚本按当前协议处理
+    pub fn with_require_hello(mut self, require_hello: bool) -> Self {
+        self.require_hello = require_hello;
+        self
+    }
+
+    /// 开启控制通道：子进程的 stdin 用于接收 `Command`，通过 `control_handle()` 发送命令
+    ///
+    /// 环境变量 `PYRUNNER_CONTROL=stdin` 告知子进程从 stdin 读取命令
+    pub fn with_control_channel(self) -> Self {
+        self.with_control_handle(ControlHandle::new())
     }
 
-    #[instrument(skip(self, listener))]
+    /// 使用外部创建的控制句柄开启控制通道
+    pub fn with_control_handle(mut self, control: ControlHandle) -> Self {
+        self.control = Some(control);
+        self
+    }
+
+    /// 同类输入最多向用户请求多少次，超过后终止子进程，密码类输入返回 `PasswordIncorrect`
+    ///
+    /// 次数通过环境变量 `PYRUNNER_INPUT_ATTEMPTS` 传给子进程
+    pub fn with_input_attempts(mut self, attempts: u32) -> Self {
+        self.input_attempts = attempts;
+        self
+    }
+
+    /// 失败后按策略重新执行子进程，每次重试前通过 `on_retry` 通知监听器
+    ///
+    /// 超时或违反协议而被终止的那次执行照常通过 `on_error` 报告；子进程自行异常退出时错误只通过
+    /// `RetryMessage` 的 `error_code`/`error_message` 告知。只有最后一次失败的错误作为返回值
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// 限制子进程的内存、CPU 时间、打开文件数和文件大小，超出限制时返回 `ResourceExhausted`
+    pub fn with_resource_limits(mut self, limits: ResourceLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn task_id(&self) -> u64 {
+        self.task_id
+    }
+
+    /// 获取取消句柄，可交给其他线程（如UI）用于终止任务
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// 获取子进程句柄，可用于挂起和恢复子进程；挂起期间不计空闲超时
+    pub fn process_handle(&self) -> ProcessHandle {
+        self.process.clone()
+    }
+
+    /// 获取控制句柄，未开启控制通道时返回 `None`
+    pub fn control_handle(&self) -> Option<ControlHandle> {
+        self.control.clone()
+    }
+
+    /// 取消任务：正在执行的 `execute` 会终止子进程并返回 `TaskCancelled`
+    pub fn abort(&self) {
+        self.cancel.cancel();
+    }
+
+    #[instrument(skip(self, listener), fields(task_id = self.task_id))]
     pub async fn execute<L>(&self, listener: &mut L) -> Result<()>
+    where
+        L: MessageListener,
+    {
+        let mut events = self.stream(Span::current());
+        while let Some(event) = events.next().await {
+            match event {
+                TaskEvent::Exit(result) => return result,
+                event => event.dispatch(listener),
+            }
+        }
+        Err(PyRunnerError::internal_error("任务事件流提前结束"))
+    }
+
+    /// 启动任务并以事件流的形式返回进度、错误、结果、文本行和退出状态
+    ///
+    /// 任务随着流被轮询而推进；执行器被复制一份，返回的流不借用 `self`
+    pub fn spawn(&self) -> TaskStream {
+        self.stream(info_span!("execute", task_id = self.task_id))
+    }
+
+    fn stream(&self, span: Span) -> TaskStream {
+        let executor = self.clone();
+        let events = EventQueue::default();
+        let mut listener = events.clone();
+        let task = async move {
+            let result = executor.run_with_retry(&mut listener).await;
+            if let Some(control) = &executor.control {
+                control.close_pending();
+            }
+            result
+        };
+        TaskStream::new(events, task.instrument(span))
+    }
+
+    async fn run_with_retry<L>(&self, listener: &mut L) -> Result<()>
+    where
+        L: MessageListener,
+    {
+        let mut attempt = 1;
+        loop {
+            let error = match self.run(listener).await {
+                Ok(()) => return Ok(()),
+                Err(error) => error,
+            };
+            let Some(policy) = self
+                .retry
+                .as_ref()
+                .filter(|policy| policy.should_retry(&error, attempt))
+            else {
+                return Err(error);
+            };
+            let delay = policy.backoff(attempt);
+            attempt += 1;
+            info!(
+                "任务执行失败，{:?}后第{}/{}次执行: {error}",
+                delay,
+                attempt,
+                policy.max_attempts()
+            );
+            listener.on_retry(RetryMessage {
+                attempt,
+                max_attempts: policy.max_attempts(),
+                error_code: error.error_code(),
+                error_message: error.to_string(),
+                delay_ms: delay.as_millis() as u64,
+            });
+            // 被任务池抢占而挂起的任务在恢复之前不能启动新的子进程
+            let ready = async {
+                tokio::time::sleep(delay).await;
+                if self.process.is_suspended() {
+                    info!("任务已挂起，恢复后再重试");
+                }
+                self.process.wait_resumed().await;
+            };
+            tokio::select! {
+                _ = ready => {}
+                _ = self.cancel.cancelled() => {
+                    warn!("任务在等待重试时被取消");
+                    return Err(PyRunnerError::TaskCancelled {
+                        task_id: self.task_id,
+                    });
+                }
+            }
+        }
+    }
+
+    async fn run<L>(&self, listener: &mut L) -> Result<()>
     where
         L: MessageListener,
     {
         info!("开始执行任务: exec: {}, argv: {:?}", self.exec, self.argv);
 
-        let mut child = Command::new(&self.exec)
+        let mut command = tokio::process::Command::new(&self.exec);
+        command
             .args(&self.argv)
+            .env(PROTOCOL_VERSION_ENV, PROTOCOL_VERSION.to_string())
+            .envs(self.envs.iter().map(|(key, value)| (key, value)))
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
-            .spawn()?;
+            .kill_on_drop(true);
+        let parent = getpid();
+        // SAFETY: 闭包在 fork 之后、exec 之前执行，只调用了异步信号安全的 setsid/prctl/getppid
+        unsafe {
+            command.pre_exec(move || isolate_child(parent));
+        }
+        if let Some(prefix) = &self.message_prefix {
+            command.env(MESSAGE_PREFIX_ENV, prefix);
+        }
+        if self.control.is_some() {
+            command
+                .env(CONTROL_ENV, "stdin")
+                .env(INPUT_ATTEMPTS_ENV, self.input_attempts.to_string())
+                .stdin(Stdio::piped());
+        }
+        if let Some(dir) = &self.python_client_dir {
+            install_python_client(dir)?;
+            command.env("PYTHONPATH", self.python_path(dir)?);
+        }
+        let message_pipe = match self.message_fd {
+            Some(target_fd) => {
+                let (reader, writer) = std::io::pipe()?;
+                let source_fd = writer.as_raw_fd();
+                command.env(MESSAGE_FD_ENV, target_fd.to_string());
+                // SAFETY: 闭包在 fork 之后、exec 之前执行，只调用了异步信号安全的 dup2/fcntl
+                unsafe {
+                    command.pre_exec(move || redirect_fd(source_fd, target_fd));
+                }
+                Some((reader, writer))
+            }
+            None => None,
+        };
+        if !self.limits.is_empty() {
+            let limits = self.limits;
+            // SAFETY: 闭包在 fork 之后、exec 之前执行，只调用了异步信号安全的 getrlimit/setrlimit
+            unsafe {
+                command.pre_exec(move || limits.apply());
+            }
+        }
+        let mut child = spawn_child(command).await?;
         info!("子进程已创建: pid: {:?}", child.id());
+        let _attached = child.id().map(|pid| self.process.attach(pid));
+
+        // 父进程需要关闭写端，子进程退出后读端才能读到 EOF
+        let mut message_lines = match message_pipe {
+            Some((reader, writer)) => {
+                drop(writer);
+                Some(BufReader::new(pipe::Receiver::from_owned_fd(reader.into())?).lines())
+            }
+            None => None,
+        };
 
         let mut stdout_lines =
             BufReader::new(child.stdout.take().ok_or_else(|| {
@@ -40,15 +423,68 @@ impl TaskExecutor {
             })?)
             .lines();
 
+        let mut stdin = child.stdin.take();
+        let mut paused = false;
+        let mut cancel_requested = false;
+
         let mut stdout_done = false;
         let mut stderr_done = false;
+        let mut message_done = message_lines.is_none();
+        let mut stderr_tail = VecDeque::with_capacity(self.stderr_tail_lines);
+        let mut traceback = TracebackParser::new();
+        let mut protocol = ProtocolState::default();
+
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let mut idle_deadline = self.idle_timeout.map(|idle| Instant::now() + idle);
+
+        // 孙进程继承了输出管道时，子进程退出后管道也不会关闭，需要单独等待子进程退出
+        let pid = child.id();
+        let exited = async {
+            match pid {
+                Some(pid) => wait_exited(pid).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::pin!(exited);
+        let mut drain_deadline = None;
 
         info!("开始读取子进程输出");
-        while !(stdout_done && stderr_done) {
+        while !(stdout_done && stderr_done && message_done) {
             tokio::select! {
+                result = &mut exited, if drain_deadline.is_none() => {
+                    result?;
+                    info!("子进程已退出，结束进程组并读取剩余输出");
+                    self.process.kill_group();
+                    drain_deadline = Some(Instant::now() + OUTPUT_DRAIN_TIMEOUT);
+                }
+                _ = sleep_until(drain_deadline), if drain_deadline.is_some() => {
+                    warn!("子进程退出{OUTPUT_DRAIN_TIMEOUT:?}后输出管道仍未关闭，停止读取");
+                    break;
+                }
+                interrupt = self.interrupted(deadline, idle_deadline) => {
+                    if interrupt == Interrupt::IdleTimeout
+                        && let Some(extended) = self.idle_extension()
+                    {
+                        idle_deadline = Some(extended);
+                        continue;
+                    }
+                    return self.interrupt_child(&mut child, interrupt, listener).await;
+                }
                 result = stdout_lines.next_line(), if !stdout_done => {
                     match result {
-                        Ok(Some(line)) => listener.on_message(line),
+                        Ok(Some(line)) if self.message_fd.is_some() => {
+                            listener.on_text(line, OutputStream::Stdout);
+                        }
+                        Ok(Some(line)) => {
+                            match self.handle_message_line(line, &mut protocol, listener) {
+                                Ok(true) if !paused => {
+                                    idle_deadline = self.idle_timeout.map(|idle| Instant::now() + idle);
+                                }
+                                Ok(true) => {}
+                                Ok(false) => {}
+                                Err(e) => return self.reject_child(&mut child, e, listener).await,
+                            }
+                        }
                         Ok(None) => {
                             stdout_done = true;
                             info!("读取子进程stdout结束");
@@ -59,9 +495,64 @@ impl TaskExecutor {
                         },
                     }
                 }
+                result = next_line(&mut message_lines), if !message_done => {
+                    match result {
+                        Ok(Some(line)) => {
+                            match self.handle_message_line(line, &mut protocol, listener) {
+                                Ok(true) if !paused => {
+                                    idle_deadline = self.idle_timeout.map(|idle| Instant::now() + idle);
+                                }
+                                Ok(true) => {}
+                                Ok(false) => {}
+                                Err(e) => return self.reject_child(&mut child, e, listener).await,
+                            }
+                        }
+                        Ok(None) => {
+                            message_done = true;
+                            info!("读取子进程消息管道结束");
+                        },
+                        Err(e) => {
+                            error!("读取子进程消息管道失败: {e}");
+                            return Err(PyRunnerError::IoError(e));
+                        },
+                    }
+                }
+                command = next_command(self.control.as_ref()), if stdin.is_some() => {
+                    // 暂停期间脚本不会发送进度，空闲超时随之暂停
+                    match command {
+                        Command::Pause => {
+                            paused = true;
+                            idle_deadline = None;
+                        }
+                        Command::Resume => {
+                            paused = false;
+                            idle_deadline = self.idle_timeout.map(|idle| Instant::now() + idle);
+                        }
+                        // 放弃输入与协作式取消相同，由脚本自行退出
+                        Command::Cancel | Command::Input(InputMessage { value: None, .. }) => {
+                            cancel_requested = true;
+                        }
+                        Command::Request(_) | Command::Input(_) => {}
+                    }
+                    if let Some(writer) = stdin.as_mut()
+                        && let Err(e) = write_command(writer, &command).await
+                    {
+                        warn!("发送控制命令失败，关闭控制通道: {e}");
+                        stdin = None;
+                    }
+                }
                 result = stderr_lines.next_line(), if !stderr_done => {
                     match result {
-                        Ok(Some(line)) => listener.on_message(line),
+                        Ok(Some(line)) => {
+                            traceback.feed(&line);
+                            if self.stderr_tail_lines > 0 {
+                                if stderr_tail.len() == self.stderr_tail_lines {
+                                    stderr_tail.pop_front();
+                                }
+                                stderr_tail.push_back(line.clone());
+                            }
+                            listener.on_stderr(line);
+                        }
                         Ok(None) => {
                             stderr_done = true;
                             info!("读取子进程stderr结束");
@@ -76,17 +567,359 @@ impl TaskExecutor {
         }
         info!("读取子进程输出结束");
 
+        drop(stdin);
         info!("开始回收子进程");
-        let status = child.wait().await?;
+        let (status, cpu_time) = loop {
+            tokio::select! {
+                reaped = self.reap_child(&mut child) => break reaped?,
+                interrupt = self.interrupted(deadline, idle_deadline) => {
+                    if interrupt == Interrupt::IdleTimeout
+                        && let Some(extended) = self.idle_extension()
+                    {
+                        idle_deadline = Some(extended);
+                        continue;
+                    }
+                    return self.interrupt_child(&mut child, interrupt, listener).await;
+                }
+            }
+        };
         if status.success() {
             info!("回收子进程成功: exit_status: {:?}", status);
         } else {
             error!("回收子进程失败: exit_status: {:?}", status);
-            return Err(PyRunnerError::ProcessExecutionFailed(status));
+            if cancel_requested {
+                return Err(PyRunnerError::TaskCancelled {
+                    task_id: self.task_id,
+                });
+            }
+            if let Some(resource) = self
+                .limits
+                .exhausted(&status, traceback.traceback(), cpu_time)
+            {
+                error!("子进程超出资源限制: {resource}");
+                return Err(PyRunnerError::ResourceExhausted { resource });
+            }
+            if protocol.password_incorrect {
+                return Err(PyRunnerError::PasswordIncorrect {
+                    attempts: protocol.attempts(InputKind::Password),
+                });
+            }
+            if let Some(traceback) = traceback.take() {
+                error!("Python异常: {traceback:?}");
+                return Err(traceback.into_error());
+            }
+            return Err(PyRunnerError::ProcessExecutionFailed {
+                status,
+                stderr_tail: stderr_tail.into(),
+            });
         }
 
         Ok(())
     }
+
+    /// 将 `dir` 放在子进程原有 `PYTHONPATH` 的最前面
+    fn python_path(&self, dir: &std::path::Path) -> Result<OsString> {
+        let inherited = match self.envs.iter().rev().find(|(key, _)| key == "PYTHONPATH") {
+            Some((_, value)) => Some(OsString::from(value)),
+            None => std::env::var_os("PYTHONPATH"),
+        };
+        let mut paths = vec![dir.to_path_buf()];
+        if let Some(inherited) = inherited {
+            paths.extend(std::env::split_paths(&inherited));
+        }
+        std::env::join_paths(paths)
+            .map_err(|e| PyRunnerError::config_error(format!("PYTHONPATH无效: {e}")))
+    }
+
+    /// 处理一行可能是协议消息的输出，返回该行是否为协议消息
+    ///
+    /// 握手失败或输入超过尝试次数时返回错误，调用方应终止子进程
+    fn handle_message_line<L>(
+        &self,
+        line: String,
+        protocol: &mut ProtocolState,
+        listener: &mut L,
+    ) -> Result<bool>
+    where
+        L: MessageListener,
+    {
+        let payload = match &self.message_prefix {
+            Some(prefix) => match line.strip_prefix(prefix.as_str()) {
+                Some(payload) => payload,
+                None => {
+                    listener.on_text(line, OutputStream::Stdout);
+                    return Ok(false);
+                }
+            },
+            None => line.as_str(),
+        };
+
+        match Message::parse(payload) {
+            // 重试事件只由 runner 产生，子进程不能冒充
+            Ok(Some(Message::Retry(_))) => {
+                warn!("忽略子进程发送的Retry消息: {payload}");
+                Ok(false)
+            }
+            Ok(Some(message)) => {
+                self.check_handshake(&message, &mut protocol.handshake_done)?;
+                match message {
+                    Message::NeedsInput(request) => {
+                        let attempts = protocol.input_attempts.entry(request.kind).or_default();
+                        *attempts += 1;
+                        if *attempts > self.input_attempts {
+                            return Err(match request.kind {
+                                InputKind::Password => PyRunnerError::PasswordIncorrect {
+                                    attempts: self.input_attempts,
+                                },
+                                kind => PyRunnerError::InvalidParameter {
+                                    parameter: format!("{kind:?}"),
+                                    value: format!("已尝试{}次", self.input_attempts),
+                                },
+                            });
+                        }
+                        let responder = InputResponder::new(request.id, self.control.clone());
+                        listener.on_needs_input(request, responder);
+                    }
+                    Message::Response(response) => {
+                        if let Some(control) = &self.control {
+                            control.resolve(&response);
+                        }
+                        listener.dispatch(Message::Response(response));
+                    }
+                    Message::Error(error) => {
+                        // 脚本以错误码报告密码错误，异常类型由脚本决定，不能据此判断
+                        if error.error_code == PASSWORD_INCORRECT_CODE {
+                            protocol.password_incorrect = true;
+                        }
+                        listener.on_error(error);
+                    }
+                    message => listener.dispatch(message),
+                }
+                Ok(true)
+            }
+            // 未知类型的消息不算协议消息，不刷新空闲超时
+            Ok(None) => {
+                listener.on_text(line, OutputStream::Stdout);
+                Ok(false)
+            }
+            Err(e) if self.message_prefix.is_some() || looks_like_message(payload) => {
+                listener.on_parse_error(line, e);
+                Ok(false)
+            }
+            Err(_) => {
+                listener.on_text(line, OutputStream::Stdout);
+                Ok(false)
+            }
+        }
+    }
+
+    /// 检查子进程的第一条消息：`Hello` 需要版本兼容，其余消息视为未握手的旧脚本
+    fn check_handshake(&self, message: &Message, handshake_done: &mut bool) -> Result<()> {
+        if std::mem::replace(handshake_done, true) {
+            if matches!(message, Message::Hello(_)) {
+                warn!("重复的Hello消息");
+            }
+            return Ok(());
+        }
+        match message {
+            Message::Hello(hello) => {
+                hello.check_version()?;
+                info!(
+                    "握手成功: protocol_version: {}, script: {:?}",
+                    hello.protocol_version, hello.script
+                );
+                Ok(())
+            }
+            _ if self.require_hello => Err(PyRunnerError::config_error(
+                "子进程发送的第一条消息不是Hello，可能未使用支持握手的pyrunner客户端",
+            )),
+            _ => {
+                info!("子进程未发送Hello消息，按当前协议处理");
+                Ok(())
+            }
+        }
+    }
+
+    /// 等待取消或超时发生，未配置的超时永不触发
+    async fn interrupted(
+        &self,
+        deadline: Option<Instant>,
+        idle_deadline: Option<Instant>,
+    ) -> Interrupt {
+        tokio::select! {
+            _ = self.cancel.cancelled() => Interrupt::Cancelled,
+            _ = sleep_until(deadline) => Interrupt::Timeout,
+            _ = sleep_until(idle_deadline) => Interrupt::IdleTimeout,
+        }
+    }
+
+    /// 子进程挂起中或刚恢复时顺延空闲超时，返回新的截止时间
+    fn idle_extension(&self) -> Option<Instant> {
+        let idle = self.idle_timeout?;
+        if self.process.is_suspended() {
+            return Some(Instant::now() + idle);
+        }
+        let deadline = self.process.resumed_at()? + idle;
+        (deadline > Instant::now()).then_some(deadline)
+    }
+
+    async fn interrupt_child<L>(
+        &self,
+        child: &mut Child,
+        interrupt: Interrupt,
+        listener: &mut L,
+    ) -> Result<()>
+    where
+        L: MessageListener,
+    {
+        let error = match interrupt {
+            Interrupt::Cancelled => {
+                warn!("任务被取消，开始终止子进程");
+                PyRunnerError::TaskCancelled {
+                    task_id: self.task_id,
+                }
+            }
+            Interrupt::Timeout => {
+                warn!("任务执行超时({:?})，开始终止子进程", self.timeout);
+                PyRunnerError::task_timeout(self.task_id)
+            }
+            Interrupt::IdleTimeout => {
+                warn!("{:?}内未收到任何消息，开始终止子进程", self.idle_timeout);
+                PyRunnerError::task_timeout(self.task_id)
+            }
+        };
+        let status = self.terminate_child(child).await?;
+        info!("子进程已终止: exit_status: {:?}", status);
+        if interrupt != Interrupt::Cancelled {
+            listener.on_error(ErrorMessage::from(&error));
+        }
+        Err(error)
+    }
+
+    /// 子进程违反协议时终止它，并将错误同时报告给监听器
+    async fn reject_child<L>(
+        &self,
+        child: &mut Child,
+        error: PyRunnerError,
+        listener: &mut L,
+    ) -> Result<()>
+    where
+        L: MessageListener,
+    {
+        error!("子进程违反协议，开始终止子进程: {error}");
+        let status = self.terminate_child(child).await?;
+        info!("子进程已终止: exit_status: {:?}", status);
+        listener.on_error(ErrorMessage::from(&error));
+        Err(error)
+    }
+
+    /// 先向子进程所在的进程组发送 SIGTERM，超过宽限期仍未退出则 SIGKILL，最终回收子进程
+    async fn terminate_child(&self, child: &mut Child) -> Result<ExitStatus> {
+        if let Some(pid) = child.id() {
+            let pgid = Pid::from_raw(pid as i32);
+            if let Err(e) = signal::killpg(pgid, Signal::SIGTERM) {
+                warn!("发送SIGTERM失败: {e}");
+            }
+            // 挂起的子进程收到 SIGCONT 后才会处理 SIGTERM，挂起状态留给重试时判断
+            if let Err(e) = self.process.continue_stopped() {
+                warn!("恢复挂起的子进程失败: {e}");
+            }
+            if tokio::time::timeout(self.kill_grace, wait_exited(pid))
+                .await
+                .is_err()
+            {
+                warn!("子进程未在{:?}内退出，发送SIGKILL", self.kill_grace);
+                if let Err(e) = signal::killpg(pgid, Signal::SIGKILL) {
+                    warn!("发送SIGKILL失败: {e}");
+                }
+            }
+        }
+        Ok(self.reap_child(child).await?.0)
+    }
+
+    /// 等待子进程退出，趁 pid 尚未释放结束组内残留的孙进程，再回收子进程
+    ///
+    /// 同时返回子进程消耗的 CPU 时间，只在设置了 CPU 时间限制时读取，回收之后无法再读取
+    async fn reap_child(&self, child: &mut Child) -> Result<(ExitStatus, Option<Duration>)> {
+        let mut cpu_time = None;
+        if let Some(pid) = child.id() {
+            wait_exited(pid).await?;
+            cpu_time = self.limits.cpu_time_used(pid);
+            self.process.kill_group();
+        }
+        Ok((child.wait().await?, cpu_time))
+    }
+}
+
+/// 让子进程成为新会话和进程组的首进程，并在创建它的线程退出时收到 SIGKILL，仅在 `pre_exec` 中调用
+///
+/// `PR_SET_PDEATHSIG` 跟随的是调用 fork 的线程而不是整个进程，子进程统一由 `spawn_child`
+/// 的专用线程创建，宿主崩溃时子进程随之结束
+#[cfg_attr(
+    not(any(target_os = "linux", target_os = "android")),
+    allow(unused_variables)
+)]
+fn isolate_child(parent: Pid) -> std::io::Result<()> {
+    setsid()?;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        if unsafe { libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        // 设置之前父进程已经退出时不会再收到信号，直接放弃启动
+        if nix::unistd::getppid() != parent {
+            return Err(std::io::Error::from_raw_os_error(libc::ESRCH));
+        }
+    }
+    Ok(())
+}
+
+/// 将 `source` 复制到子进程的 `target` 上，仅在 `pre_exec` 中调用
+fn redirect_fd(source: RawFd, target: RawFd) -> std::io::Result<()> {
+    if source == target {
+        // dup2 对相同的 fd 是空操作，不会清除 FD_CLOEXEC，需要手动清除
+        let flags = unsafe { libc::fcntl(source, libc::F_GETFD) };
+        if flags < 0 || unsafe { libc::fcntl(source, libc::F_SETFD, flags & !libc::FD_CLOEXEC) } < 0
+        {
+            return Err(std::io::Error::last_os_error());
+        }
+    } else if unsafe { libc::dup2(source, target) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// 读取下一行，未开启的流永远不会就绪
+async fn next_line<R>(lines: &mut Option<Lines<R>>) -> std::io::Result<Option<String>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    match lines {
+        Some(lines) => lines.next_line().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 等待下一条控制命令，未开启控制通道时永远不会就绪
+async fn next_command(control: Option<&ControlHandle>) -> Command {
+    match control {
+        Some(control) => control.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn write_command(stdin: &mut ChildStdin, command: &Command) -> std::io::Result<()> {
+    let mut line = serde_json::to_string(command)?;
+    line.push('\n');
+    stdin.write_all(line.as_bytes()).await?;
+    stdin.flush().await
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
 }
 
 #[cfg(test)]
@@ -98,6 +931,9 @@ mod tests {
         progress_count: u32,
         error_count: u32,
         result_count: u32,
+        stderr_count: u32,
+        text_count: u32,
+        parse_error_count: u32,
     }
     impl crate::listener::MessageListener for TestProgressListener {
         fn on_error(&mut self, _error: crate::ipc::ErrorMessage) {
@@ -109,6 +945,15 @@ mod tests {
         fn on_progress(&mut self, _progress: crate::ipc::ProgressMessage) {
             self.progress_count += 1;
         }
+        fn on_stderr(&mut self, _line: String) {
+            self.stderr_count += 1;
+        }
+        fn on_text(&mut self, _line: String, _stream: OutputStream) {
+            self.text_count += 1;
+        }
+        fn on_parse_error(&mut self, _line: String, _error: serde_json::Error) {
+            self.parse_error_count += 1;
+        }
     }
 
     #[tokio::test]
@@ -116,9 +961,552 @@ mod tests {
         let executor = TaskExecutor::new("python".into(), vec!["src/demo_progress.py".into()]);
 
         let mut test_listener = TestProgressListener::default();
-        let _ = executor.execute(&mut test_listener).await.unwrap();
+        executor.execute(&mut test_listener).await.unwrap();
         assert_eq!(test_listener.progress_count, 10);
         assert_eq!(test_listener.error_count, 0);
         assert_eq!(test_listener.result_count, 1);
+        assert_eq!(test_listener.text_count, 13);
+        assert_eq!(test_listener.parse_error_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_non_send_listener() {
+        // 监听器只在执行任务的线程上使用，不要求 Send
+        struct SharedListener(std::rc::Rc<std::cell::Cell<u32>>);
+        impl crate::listener::MessageListener for SharedListener {
+            fn on_progress(&mut self, _progress: crate::ipc::ProgressMessage) {
+                self.0.set(self.0.get() + 1);
+            }
+            fn on_error(&mut self, _error: crate::ipc::ErrorMessage) {}
+            fn on_result(&mut self, _result: crate::ipc::ResultMessage) {}
+        }
+
+        let count = std::rc::Rc::new(std::cell::Cell::new(0));
+        let executor = TaskExecutor::new("python".into(), vec!["src/demo_progress.py".into()]);
+        executor
+            .execute(&mut SharedListener(count.clone()))
+            .await
+            .unwrap();
+        assert_eq!(count.get(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_abort() {
+        let executor = TaskExecutor::new(
+            "python".into(),
+            vec!["-c".into(), "import time; time.sleep(30)".into()],
+        )
+        .with_task_id(7);
+        let cancel = executor.cancel_handle();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            cancel.cancel();
+        });
+
+        let start = std::time::Instant::now();
+        let mut test_listener = TestProgressListener::default();
+        let result = executor.execute(&mut test_listener).await;
+        assert!(matches!(
+            result,
+            Err(PyRunnerError::TaskCancelled { task_id: 7 })
+        ));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_abort_escalates_to_sigkill() {
+        let script = "import signal, time\n\
+                      signal.signal(signal.SIGTERM, signal.SIG_IGN)\n\
+                      print('ready', flush=True)\n\
+                      time.sleep(30)";
+        let executor = TaskExecutor::new("python".into(), vec!["-c".into(), script.into()])
+            .with_kill_grace(Duration::from_millis(200));
+        let cancel = executor.cancel_handle();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            cancel.cancel();
+        });
+
+        let start = std::time::Instant::now();
+        let mut test_listener = TestProgressListener::default();
+        let result = executor.execute(&mut test_listener).await;
+        assert!(matches!(result, Err(PyRunnerError::TaskCancelled { .. })));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let executor = TaskExecutor::new(
+            "python".into(),
+            vec!["-c".into(), "import time; time.sleep(30)".into()],
+        )
+        .with_task_id(3)
+        .with_timeout(Duration::from_millis(300));
+
+        let mut test_listener = TestProgressListener::default();
+        let result = executor.execute(&mut test_listener).await;
+        assert!(matches!(
+            result,
+            Err(PyRunnerError::TaskTimeout { task_id: 3 })
+        ));
+        assert_eq!(test_listener.error_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let script = "import json, time\n\
+                      for i in range(3):\n    \
+                          print(json.dumps({'Progress': {'done': i, 'size': 3}}), flush=True)\n    \
+                          time.sleep(0.1)\n\
+                      time.sleep(30)";
+        let executor = TaskExecutor::new("python".into(), vec!["-c".into(), script.into()])
+            .with_idle_timeout(Duration::from_secs(1));
+
+        let mut test_listener = TestProgressListener::default();
+        let result = executor.execute(&mut test_listener).await;
+        assert!(matches!(result, Err(PyRunnerError::TaskTimeout { .. })));
+        assert_eq!(test_listener.progress_count, 3);
+        assert_eq!(test_listener.error_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_suspend_pauses_idle_timeout() {
+        let script = "import json, time\n\
+                      print(json.dumps({'Progress': {'done': 1, 'size': 2}}), flush=True)\n\
+                      time.sleep(0.2)\n\
+                      print(json.dumps({'Progress': {'done': 2, 'size': 2}}), flush=True)";
+        let executor = TaskExecutor::new("python".into(), vec!["-c".into(), script.into()])
+            .with_idle_timeout(Duration::from_millis(500));
+        let process = executor.process_handle();
+        tokio::spawn(async move {
+            while process.pid().is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            assert!(process.suspend().unwrap());
+            tokio::time::sleep(Duration::from_millis(1200)).await;
+            assert!(process.resume().unwrap());
+        });
+
+        let mut test_listener = TestProgressListener::default();
+        executor.execute(&mut test_listener).await.unwrap();
+        assert_eq!(test_listener.progress_count, 2);
+        assert_eq!(executor.process_handle().pid(), None);
+    }
+
+    #[tokio::test]
+    async fn test_stderr_tail() {
+        let script = "import sys\n\
+                      for i in range(10):\n    \
+                          print(f'line {i}', file=sys.stderr)\n\
+                      sys.exit(3)";
+        let executor = TaskExecutor::new("python".into(), vec!["-c".into(), script.into()])
+            .with_stderr_tail_lines(3);
+
+        let mut test_listener = TestProgressListener::default();
+        let result = executor.execute(&mut test_listener).await;
+        assert_eq!(test_listener.stderr_count, 10);
+        match result {
+            Err(PyRunnerError::ProcessExecutionFailed {
+                status,
+                stderr_tail,
+            }) => {
+                assert_eq!(status.code(), Some(3));
+                assert_eq!(stderr_tail, vec!["line 7", "line 8", "line 9"]);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_python_error() {
+        let script = "def convert(page):\n    \
+                          raise ValueError(f'bad page: {page}')\n\
+                      convert(3)";
+        let executor = TaskExecutor::new("python".into(), vec!["-c".into(), script.into()]);
+
+        let mut test_listener = TestProgressListener::default();
+        match executor.execute(&mut test_listener).await {
+            Err(PyRunnerError::PythonError {
+                exc_type,
+                message,
+                frames,
+            }) => {
+                assert_eq!(exc_type, "ValueError");
+                assert_eq!(message, "bad page: 3");
+                assert_eq!(frames.len(), 2);
+                assert_eq!(frames[1].function, "convert");
+                assert_eq!(frames[1].line, 2);
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handled_python_error() {
+        let script = "import sys, traceback\n\
+                      try:\n    \
+                          raise ValueError('first try')\n\
+                      except ValueError:\n    \
+                          traceback.print_exc()\n\
+                      print('giving up', file=sys.stderr)\n\
+                      sys.exit(1)";
+        let executor = TaskExecutor::new("python".into(), vec!["-c".into(), script.into()]);
+
+        let mut test_listener = TestProgressListener::default();
+        match executor.execute(&mut test_listener).await {
+            Err(PyRunnerError::ProcessExecutionFailed { status, .. }) => {
+                assert_eq!(status.code(), Some(1));
+            }
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_password_incorrect_reported() {
+        let script = "import json\n\
+                      print(json.dumps({'Error': {'error_code': 4004, 'error_message': 'bad password'}}))\n\
+                      raise RuntimeError('bad password')";
+        let executor = TaskExecutor::new("python".into(), vec!["-c".into(), script.into()]);
+
+        let mut test_listener = TestProgressListener::default();
+        let result = executor.execute(&mut test_listener).await;
+        assert!(
+            matches!(result, Err(PyRunnerError::PasswordIncorrect { .. })),
+            "{result:?}"
+        );
+        assert_eq!(test_listener.error_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_python_import_failed() {
+        let executor = TaskExecutor::new(
+            "python".into(),
+            vec!["-c".into(), "import pyrunner_no_such_module".into()],
+        );
+
+        let mut test_listener = TestProgressListener::default();
+        let result = executor.execute(&mut test_listener).await;
+        assert!(matches!(
+            result,
+            Err(PyRunnerError::PythonModuleImportFailed { module }) if module == "pyrunner_no_such_module"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_parse_error() {
+        let script = "print('plain text')\n\
+                      print('{\"Progress\": {\"done\": \"x\", \"size\": 3}}')\n\
+                      print({'Progress': 1})";
+        let executor = TaskExecutor::new("python".into(), vec!["-c".into(), script.into()]);
+
+        let mut test_listener = TestProgressListener::default();
+        executor.execute(&mut test_listener).await.unwrap();
+        assert_eq!(test_listener.text_count, 1);
+        assert_eq!(test_listener.parse_error_count, 2);
+        assert_eq!(test_listener.progress_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_spawn() {
+        let script = "import json, sys\n\
+                      print('plain text')\n\
+                      print(json.dumps({'Progress': {'done': 1, 'size': 2}}))\n\
+                      print(json.dumps({'Result': {'pages': 1, 'words': 2}}))\n\
+                      print('failed', file=sys.stderr)\n\
+                      sys.exit(3)";
+        let executor = TaskExecutor::new("python".into(), vec!["-c".into(), script.into()]);
+
+        let mut events = executor.spawn();
+        let mut kinds = Vec::new();
+        let mut exit = None;
+        while let Some(event) = events.next().await {
+            match event {
+                TaskEvent::Message(message) => kinds.push(message.kind().to_string()),
+                TaskEvent::Text(line, stream) => kinds.push(format!("{stream}: {line}")),
+                TaskEvent::Exit(result) => exit = Some(result),
+                event => panic!("unexpected event: {event:?}"),
+            }
+        }
+
+        kinds.sort();
+        assert_eq!(
+            kinds,
+            vec!["Progress", "Result", "stderr: failed", "stdout: plain text"]
+        );
+        assert!(matches!(
+            exit,
+            Some(Err(PyRunnerError::ProcessExecutionFailed { .. }))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_timeout() {
+        let marker = std::env::temp_dir().join(format!("pyrunner-retry-{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+        let script = "import json, os, sys, time\n\
+                      if not os.path.exists(sys.argv[1]):\n    \
+                          open(sys.argv[1], 'w').close()\n    \
+                          time.sleep(30)\n\
+                      print(json.dumps({'Result': {'pages': 1, 'words': 2}}))";
+        let executor = TaskExecutor::new(
+            "python".into(),
+            vec![
+                "-c".into(),
+                script.into(),
+                marker.to_string_lossy().into_owned(),
+            ],
+        )
+        .with_timeout(Duration::from_secs(1))
+        .with_retry_policy(
+            RetryPolicy::new().with_backoff(Duration::from_millis(50), Duration::from_millis(50)),
+        );
+
+        let mut events = executor.spawn();
+        let mut kinds = Vec::new();
+        let mut exit = None;
+        while let Some(event) = events.next().await {
+            match event {
+                TaskEvent::Message(Message::Retry(retry)) => {
+                    assert_eq!((retry.attempt, retry.max_attempts), (2, 3));
+                    assert_eq!(retry.error_code, 1002);
+                    kinds.push("Retry");
+                }
+                TaskEvent::Message(message) => kinds.push(message.kind()),
+                TaskEvent::Exit(result) => exit = Some(result),
+                _ => {}
+            }
+        }
+        let _ = std::fs::remove_file(&marker);
+
+        assert_eq!(kinds, vec!["Error", "Retry", "Result"]);
+        assert!(matches!(exit, Some(Ok(()))));
+    }
+
+    #[tokio::test]
+    async fn test_retry_from_child_ignored() {
+        let script = "import json\n\
+                      retry = {'attempt': 2, 'max_attempts': 3, 'error_code': 1002, 'error_message': '', 'delay_ms': 0}\n\
+                      print(json.dumps({'Retry': retry}))\n\
+                      print(json.dumps({'Result': {'pages': 1, 'words': 2}}))";
+        let executor = TaskExecutor::new("python".into(), vec!["-c".into(), script.into()]);
+
+        let mut events = executor.spawn();
+        let mut kinds = Vec::new();
+        while let Some(event) = events.next().await {
+            match event {
+                TaskEvent::Message(message) => kinds.push(message.kind().to_string()),
+                TaskEvent::Text(line, stream) => kinds.push(format!("{stream}: {line}")),
+                TaskEvent::Exit(result) => result.unwrap(),
+                event => panic!("unexpected event: {event:?}"),
+            }
+        }
+        assert_eq!(kinds, vec!["Result"]);
+    }
+
+    /// 进程已退出或只剩僵尸进程
+    async fn exited(pid: u32) -> bool {
+        let stat = format!("/proc/{pid}/stat");
+        for _ in 0..50 {
+            match std::fs::read_to_string(&stat) {
+                Ok(stat) if !stat.contains(") Z ") => {}
+                _ => return true,
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        false
+    }
+
+    #[tokio::test]
+    async fn test_kill_process_group() {
+        let script = "import subprocess, sys, time\n\
+                      stubborn = 'import signal, time; signal.signal(signal.SIGTERM, signal.SIG_IGN); time.sleep(30)'\n\
+                      for argv in (['sleep', '30'], [sys.executable, '-c', stubborn]):\n    \
+                          child = subprocess.Popen(argv, stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL)\n    \
+                          print(child.pid, flush=True)\n\
+                      if sys.argv[1] == 'hang':\n    \
+                          time.sleep(30)";
+        for mode in ["hang", "exit"] {
+            let executor = TaskExecutor::new(
+                "python".into(),
+                vec!["-c".into(), script.into(), mode.into()],
+            )
+            .with_timeout(Duration::from_secs(1))
+            .with_kill_grace(Duration::from_millis(200));
+
+            let mut events = executor.spawn();
+            let mut pids = Vec::new();
+            let mut exit = None;
+            while let Some(event) = events.next().await {
+                match event {
+                    TaskEvent::Text(line, OutputStream::Stdout) => pids.push(line.parse().unwrap()),
+                    TaskEvent::Exit(result) => exit = Some(result),
+                    _ => {}
+                }
+            }
+
+            match mode {
+                "hang" => assert!(matches!(exit, Some(Err(PyRunnerError::TaskTimeout { .. })))),
+                _ => assert!(matches!(exit, Some(Ok(())))),
+            }
+            assert_eq!(pids.len(), 2);
+            for pid in pids {
+                assert!(exited(pid).await, "{mode}: grandchild {pid} survived");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_grandchild_inherits_stdout() {
+        // 孙进程继承 stdout/stderr，子进程退出后管道仍然打开；start_new_session 的孙进程还脱离了进程组
+        let script = "import subprocess, sys\n\
+                      session = sys.argv[1] == 'session'\n\
+                      child = subprocess.Popen(['sleep', '30'], start_new_session=session)\n\
+                      print(child.pid, flush=True)";
+        for mode in ["group", "session"] {
+            let executor = TaskExecutor::new(
+                "python".into(),
+                vec!["-c".into(), script.into(), mode.into()],
+            );
+            let start = Instant::now();
+            let mut events = executor.spawn();
+            let mut pids = Vec::new();
+            let mut exit = None;
+            while let Some(event) = events.next().await {
+                match event {
+                    TaskEvent::Text(line, OutputStream::Stdout) => pids.push(line.parse().unwrap()),
+                    TaskEvent::Exit(result) => exit = Some(result),
+                    _ => {}
+                }
+            }
+
+            assert!(matches!(exit, Some(Ok(()))), "{mode}: {exit:?}");
+            assert!(start.elapsed() < Duration::from_secs(5), "{mode}");
+            assert_eq!(pids.len(), 1);
+            let pid: u32 = pids[0];
+            if mode == "group" {
+                assert!(exited(pid).await, "grandchild {pid} survived");
+            } else {
+                signal::kill(Pid::from_raw(pid as i32), Signal::SIGKILL).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unknown_kind_is_text() {
+        let script = "import json, time\n\
+                      for i in range(30):\n    \
+                          print(json.dumps({'status': 'ok'}), flush=True)\n    \
+                          time.sleep(0.1)";
+        let executor = TaskExecutor::new("python".into(), vec!["-c".into(), script.into()])
+            .with_idle_timeout(Duration::from_millis(500));
+
+        let mut test_listener = TestProgressListener::default();
+        let result = executor.execute(&mut test_listener).await;
+        assert!(matches!(result, Err(PyRunnerError::TaskTimeout { .. })));
+        assert!(test_listener.text_count >= 3);
+        assert_eq!(test_listener.parse_error_count, 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_child_outlives_polling_thread() {
+        let script = "import time\n\
+                      print('ready', flush=True)\n\
+                      time.sleep(0.5)\n\
+                      print('done', flush=True)";
+        let executor = TaskExecutor::new("python".into(), vec!["-c".into(), script.into()]);
+        // 在很快退出的线程上启动子进程，子进程不应随该线程一起结束
+        let handle = tokio::runtime::Handle::current();
+        let mut events = std::thread::spawn(move || {
+            let mut events = executor.spawn();
+            let first = handle.block_on(events.next());
+            assert!(matches!(first, Some(TaskEvent::Text(line, _)) if line == "ready"));
+            events
+        })
+        .join()
+        .unwrap();
+
+        let mut lines = Vec::new();
+        let mut exit = None;
+        while let Some(event) = events.next().await {
+            match event {
+                TaskEvent::Text(line, _) => lines.push(line),
+                TaskEvent::Exit(result) => exit = Some(result),
+                _ => {}
+            }
+        }
+        assert_eq!(lines, vec!["done"]);
+        assert!(matches!(exit, Some(Ok(()))), "{exit:?}");
+    }
+
+    #[tokio::test]
+    async fn test_message_prefix() {
+        let script = "import json, os\n\
+                      prefix = os.environ['PYRUNNER_MESSAGE_PREFIX']\n\
+                      print(json.dumps({'Progress': {'done': 1, 'size': 2}}))\n\
+                      print(prefix + json.dumps({'Progress': {'done': 2, 'size': 2}}))\n\
+                      print(prefix + '{broken')";
+        let executor = TaskExecutor::new("python".into(), vec!["-c".into(), script.into()])
+            .with_message_prefix(DEFAULT_MESSAGE_PREFIX);
+
+        let mut test_listener = TestProgressListener::default();
+        executor.execute(&mut test_listener).await.unwrap();
+        assert_eq!(test_listener.progress_count, 1);
+        assert_eq!(test_listener.text_count, 1);
+        assert_eq!(test_listener.parse_error_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_message_fd() {
+        let script = "import json, os\n\
+                      out = os.fdopen(int(os.environ['PYRUNNER_MESSAGE_FD']), 'w', buffering=1)\n\
+                      print(json.dumps({'Progress': {'done': 1, 'size': 2}}))\n\
+                      out.write(json.dumps({'Progress': {'done': 2, 'size': 2}}) + '\\n')\n\
+                      out.write(json.dumps({'Result': {'pages': 1, 'words': 2}}) + '\\n')";
+        let executor = TaskExecutor::new("python".into(), vec!["-c".into(), script.into()])
+            .with_message_fd(DEFAULT_MESSAGE_FD);
+
+        let mut test_listener = TestProgressListener::default();
+        executor.execute(&mut test_listener).await.unwrap();
+        assert_eq!(test_listener.progress_count, 1);
+        assert_eq!(test_listener.result_count, 1);
+        assert_eq!(test_listener.text_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_handshake() {
+        let script = "import json, os\n\
+                      version = int(os.environ['PYRUNNER_PROTOCOL_VERSION'])\n\
+                      print(json.dumps({'Hello': {'protocol_version': version, 'script': 'x.py'}}))\n\
+                      print(json.dumps({'Heartbeat': {'seq': 1}}))\n\
+                      print(json.dumps({'Progress': {'done': 1, 'size': 1}}))";
+        let executor = TaskExecutor::new("python".into(), vec!["-c".into(), script.into()])
+            .with_require_hello(true);
+
+        let mut test_listener = TestProgressListener::default();
+        executor.execute(&mut test_listener).await.unwrap();
+        assert_eq!(test_listener.progress_count, 1);
+        assert_eq!(test_listener.parse_error_count, 0);
+        // 未知类型的消息按普通文本交给监听器
+        assert_eq!(test_listener.text_count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_rejected() {
+        let script = "import json, time\n\
+                      print(json.dumps({'Hello': {'protocol_version': 999}}), flush=True)\n\
+                      time.sleep(30)";
+        let executor = TaskExecutor::new("python".into(), vec!["-c".into(), script.into()]);
+
+        let mut test_listener = TestProgressListener::default();
+        let result = executor.execute(&mut test_listener).await;
+        assert!(matches!(result, Err(PyRunnerError::ConfigError { .. })));
+        assert_eq!(test_listener.error_count, 1);
+
+        let executor = TaskExecutor::new("python".into(), vec!["src/demo_progress.py".into()])
+            .with_require_hello(true);
+        let mut test_listener = TestProgressListener::default();
+        let result = executor.execute(&mut test_listener).await;
+        assert!(matches!(result, Err(PyRunnerError::ConfigError { .. })));
+        assert_eq!(test_listener.progress_count, 0);
     }
 }
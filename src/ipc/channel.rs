@@ -20,28 +20,29 @@ mod tests {
     use super::*;
     use crate::ipc::message::{ErrorMessage, ProgressMessage, ResultMessage};
 
-    #[test]
-    fn test_create_channel() {
-        #[derive(Default)]
-        struct TestProgressListener {
-            progress_count: u32,
-            error_count: u32,
-            result_count: u32,
+    #[derive(Default)]
+    struct TestProgressListener {
+        progress_count: u32,
+        error_count: u32,
+        result_count: u32,
+    }
+    impl MessageListener for TestProgressListener {
+        fn on_progress(&mut self, progress: ProgressMessage) {
+            self.progress_count += 1;
+            println!("on_progress_update: {progress:?}");
         }
-        impl MessageListener for TestProgressListener {
-            fn on_progress(&mut self, progress: ProgressMessage) {
-                self.progress_count += 1;
-                println!("on_progress_update: {progress:?}");
-            }
-            fn on_error(&mut self, error: ErrorMessage) {
-                self.error_count += 1;
-                println!("on_error: {error:?}");
-            }
-            fn on_result(&mut self, result: ResultMessage) {
-                self.result_count += 1;
-                println!("on_result: {result:?}");
-            }
+        fn on_error(&mut self, error: ErrorMessage) {
+            self.error_count += 1;
+            println!("on_error: {error:?}");
         }
+        fn on_result(&mut self, result: ResultMessage) {
+            self.result_count += 1;
+            println!("on_result: {result:?}");
+        }
+    }
+
+    #[test]
+    fn test_create_channel() {
         let test_listener = Arc::new(Mutex::new(TestProgressListener::default()));
         let (sender, receiver) = create_message_channel(test_listener.clone());
 
@@ -56,4 +57,30 @@ mod tests {
         assert_eq!(guard.error_count, 1);
         assert_eq!(guard.result_count, 1);
     }
+
+    #[test]
+    fn test_executor_forwards_to_channel() {
+        let test_listener = Arc::new(Mutex::new(TestProgressListener::default()));
+        let (mut sender, receiver) = create_message_channel(test_listener.clone());
+
+        // 监听器不要求 Send，接收端留在当前线程，任务在另一个线程执行
+        let executor = crate::executor::TaskExecutor::new(
+            "python".into(),
+            vec!["src/demo_progress.py".into()],
+        );
+        let worker = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            runtime.block_on(executor.execute(&mut sender))
+        });
+        receiver.start_listening();
+        worker.join().unwrap().unwrap();
+
+        let guard = test_listener.lock().unwrap();
+        assert_eq!(guard.progress_count, 10);
+        assert_eq!(guard.error_count, 0);
+        assert_eq!(guard.result_count, 1);
+    }
 }
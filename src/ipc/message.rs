@@ -1,17 +1,141 @@
 use crate::error::PyRunnerError;
 use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+/// 当前实现的协议版本
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// 仍然兼容的最低协议版本
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum Message {
     Progress(ProgressMessage),
     Error(ErrorMessage),
     Result(ResultMessage),
+    Output(OutputMessage),
+    Hello(HelloMessage),
+    Response(ResponseMessage),
+    NeedsInput(NeedsInputMessage),
+    Retry(RetryMessage),
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq)]
+/// 任务失败后 runner 即将重新执行子进程，由 runner 产生；子进程输出的 `Retry` 消息会被忽略
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RetryMessage {
+    /// 即将开始的是第几次执行，从 2 开始
+    pub attempt: u32,
+    pub max_attempts: u32,
+    /// 导致重试的错误
+    pub error_code: i32,
+    pub error_message: String,
+    /// 重新执行前等待的毫秒数
+    pub delay_ms: u64,
+}
+
+/// runner 通过子进程 stdin 发送的控制命令，每行一条 JSON
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum Command {
+    /// 请求脚本在下一个检查点暂停
+    Pause,
+    Resume,
+    /// 协作式取消，由脚本自行清理后退出
+    Cancel,
+    Request(RequestMessage),
+    Input(InputMessage),
+}
+
+/// 脚本运行中需要用户输入，例如打开加密 PDF 时需要密码
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NeedsInputMessage {
+    pub id: u64,
+    pub kind: InputKind,
+    #[serde(default)]
+    pub prompt: String,
+    /// 同类输入的第几次尝试，从 1 开始
+    #[serde(default)]
+    pub attempt: u32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum InputKind {
+    Password,
+    Text,
+    #[serde(other)]
+    Other,
+}
+
+/// 对 `NeedsInput` 的应答，`value` 为 `None` 表示用户放弃输入，脚本应取消任务
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct InputMessage {
+    pub id: u64,
+    pub value: Option<String>,
+}
+
+/// 发给脚本的请求，脚本以相同 `id` 的 `Response` 应答
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RequestMessage {
+    pub id: u64,
+    #[serde(default, with = "json_value")]
+    pub payload: serde_json::Value,
+}
+
+/// 脚本对 `Request` 的应答，`error` 不为空表示脚本无法处理该请求
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ResponseMessage {
+    pub id: u64,
+    #[serde(default, with = "json_value")]
+    pub payload: serde_json::Value,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// 子进程发送的第一条消息，声明协议版本、脚本名称和支持的能力
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HelloMessage {
+    pub protocol_version: u32,
+    #[serde(default)]
+    pub script: Option<String>,
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+/// 进度的附加字段都是可选的，只包含 `done`/`size` 的旧格式仍可解析
+///
+/// 因为包含阶段名称和描述文本，`ProgressMessage` 不再实现 `Copy`，需要保留副本时调用 `clone()`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 pub struct ProgressMessage {
     pub done: u64,
     pub size: u64,
+    /// 当前阶段名称，例如 "解析"、"排版"
+    #[serde(default)]
+    pub stage: Option<String>,
+    /// 当前阶段序号，从 1 开始
+    #[serde(default)]
+    pub stage_index: Option<u32>,
+    #[serde(default)]
+    pub stage_count: Option<u32>,
+    /// 面向用户的进度描述
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub unit: Option<ProgressUnit>,
+    /// 无法给出总量时为 true，此时 `size` 没有意义
+    #[serde(default)]
+    pub indeterminate: bool,
+}
+
+/// `done`/`size` 的计量单位
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProgressUnit {
+    Bytes,
+    Pages,
+    Items,
+    /// 无法识别的单位，保证新脚本不会因为单位而解析失败
+    #[serde(other)]
+    Other,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -26,10 +150,190 @@ pub struct ResultMessage {
     pub words: u64,
 }
 
+/// 通用的任务结果：任意 JSON 值以及输出文件路径
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OutputMessage {
+    #[serde(default, with = "json_value")]
+    pub value: serde_json::Value,
+    #[serde(default)]
+    pub artifacts: Vec<String>,
+}
+
 #[allow(dead_code)]
 impl ProgressMessage {
     pub fn new(done: u64, size: u64) -> Self {
-        Self { done, size }
+        Self {
+            done,
+            size,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_stage<S: Into<String>>(mut self, stage: S, index: u32, count: u32) -> Self {
+        self.stage = Some(stage.into());
+        self.stage_index = Some(index);
+        self.stage_count = Some(count);
+        self
+    }
+
+    pub fn with_message<S: Into<String>>(mut self, message: S) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    pub fn with_unit(mut self, unit: ProgressUnit) -> Self {
+        self.unit = Some(unit);
+        self
+    }
+
+    pub fn with_indeterminate(mut self, indeterminate: bool) -> Self {
+        self.indeterminate = indeterminate;
+        self
+    }
+
+    /// 完成比例 `[0, 1]`，总量未知时返回 `None`
+    pub fn fraction(&self) -> Option<f64> {
+        if self.indeterminate || self.size == 0 {
+            None
+        } else {
+            Some((self.done as f64 / self.size as f64).min(1.0))
+        }
+    }
+}
+
+impl Message {
+    /// 当前版本可以识别的消息类型
+    pub const KINDS: &[&str] = &[
+        "Progress",
+        "Error",
+        "Result",
+        "Output",
+        "Hello",
+        "Response",
+        "NeedsInput",
+        "Retry",
+    ];
+
+    /// 消息类型名称，与 JSON 中的键一致
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Progress(_) => "Progress",
+            Self::Error(_) => "Error",
+            Self::Result(_) => "Result",
+            Self::Output(_) => "Output",
+            Self::Hello(_) => "Hello",
+            Self::Response(_) => "Response",
+            Self::NeedsInput(_) => "NeedsInput",
+            Self::Retry(_) => "Retry",
+        }
+    }
+
+    /// 解析一行 JSON 消息
+    ///
+    /// 形如 `{"Kind": ...}` 但类型未知的消息返回 `Ok(None)`，新版脚本发出的新消息不会导致旧版 runner 报错；
+    /// 调用方应把这样的行当作普通文本处理，脚本输出的 `{"status": "ok"}` 之类的 JSON 也属于此类
+    pub fn parse(payload: &str) -> serde_json::Result<Option<Self>> {
+        match serde_json::from_str(payload) {
+            Ok(message) => Ok(Some(message)),
+            Err(e) => match unknown_kind(payload) {
+                Some(kind) => {
+                    debug!("忽略未知类型的消息: {kind}");
+                    Ok(None)
+                }
+                None => Err(e),
+            },
+        }
+    }
+}
+
+/// 只有一个键且键名不是已知消息类型的 JSON 对象视为未知消息
+fn unknown_kind(payload: &str) -> Option<String> {
+    let serde_json::Value::Object(object) = serde_json::from_str(payload).ok()? else {
+        return None;
+    };
+    if object.len() != 1 {
+        return None;
+    }
+    let kind = object.keys().next()?;
+    (!Message::KINDS.contains(&kind.as_str())).then(|| kind.clone())
+}
+
+impl HelloMessage {
+    pub fn new(protocol_version: u32) -> Self {
+        Self {
+            protocol_version,
+            script: None,
+            capabilities: Vec::new(),
+        }
+    }
+
+    pub fn with_script<S: Into<String>>(mut self, script: S) -> Self {
+        self.script = Some(script.into());
+        self
+    }
+
+    pub fn with_capability<S: Into<String>>(mut self, capability: S) -> Self {
+        self.capabilities.push(capability.into());
+        self
+    }
+
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+
+    /// 子进程的协议版本必须在 `[MIN_PROTOCOL_VERSION, PROTOCOL_VERSION]` 之内
+    ///
+    /// 子进程可以通过环境变量 `PYRUNNER_PROTOCOL_VERSION` 得知 runner 的版本并主动降级
+    pub fn check_version(&self) -> Result<(), PyRunnerError> {
+        let version = self.protocol_version;
+        if version > PROTOCOL_VERSION {
+            Err(PyRunnerError::config_error(format!(
+                "子进程协议版本({version})高于runner支持的版本({PROTOCOL_VERSION})，请升级runner或让脚本降级"
+            )))
+        } else if version < MIN_PROTOCOL_VERSION {
+            Err(PyRunnerError::config_error(format!(
+                "子进程协议版本({version})过旧，runner最低支持版本({MIN_PROTOCOL_VERSION})"
+            )))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl RequestMessage {
+    pub fn new(id: u64, payload: serde_json::Value) -> Self {
+        Self { id, payload }
+    }
+}
+
+impl ResponseMessage {
+    pub fn new(id: u64, payload: serde_json::Value) -> Self {
+        Self {
+            id,
+            payload,
+            error: None,
+        }
+    }
+
+    pub fn with_error<S: Into<String>>(mut self, error: S) -> Self {
+        self.error = Some(error.into());
+        self
+    }
+}
+
+impl NeedsInputMessage {
+    pub fn new<S: Into<String>>(id: u64, kind: InputKind, prompt: S) -> Self {
+        Self {
+            id,
+            kind,
+            prompt: prompt.into(),
+            attempt: 1,
+        }
+    }
+
+    pub fn with_attempt(mut self, attempt: u32) -> Self {
+        self.attempt = attempt;
+        self
     }
 }
 
@@ -59,6 +363,39 @@ impl ResultMessage {
     }
 }
 
+impl OutputMessage {
+    pub fn new(value: serde_json::Value, artifacts: Vec<String>) -> Self {
+        Self { value, artifacts }
+    }
+}
+
+/// IPC 通道使用的 bincode 不支持反序列化 `serde_json::Value`，非文本格式下改为以 JSON 字符串传输
+mod json_value {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error as _};
+
+    pub fn serialize<S: Serializer>(
+        value: &serde_json::Value,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            value.serialize(serializer)
+        } else {
+            serializer.serialize_str(&value.to_string())
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<serde_json::Value, D::Error> {
+        if deserializer.is_human_readable() {
+            serde_json::Value::deserialize(deserializer)
+        } else {
+            let json = String::deserialize(deserializer)?;
+            serde_json::from_str(&json).map_err(D::Error::custom)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -97,6 +434,34 @@ mod tests {
         assert_eq!(deserialized.size, 100);
     }
 
+    #[test]
+    fn test_progress_extended() {
+        let deserialized: ProgressMessage =
+            serde_json::from_str(r#"{"done": 3, "size": 10}"#).unwrap();
+        assert_eq!(deserialized, ProgressMessage::new(3, 10));
+        assert_eq!(deserialized.fraction(), Some(0.3));
+
+        let progress = ProgressMessage::new(2, 0)
+            .with_stage("排版", 2, 3)
+            .with_message("正在处理第 2 页")
+            .with_unit(ProgressUnit::Pages)
+            .with_indeterminate(true);
+        let message = Message::Progress(progress.clone());
+        let serialized = serde_json::to_string(&message).unwrap();
+        println!("Message(Progress(ProgressMessage)) extended serialized: {serialized}");
+        let deserialized: Message = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, message);
+        assert_eq!(progress.fraction(), None);
+
+        let encoded = bincode::serialize(&message).unwrap();
+        let decoded: Message = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded, message);
+
+        let deserialized: ProgressMessage =
+            serde_json::from_str(r#"{"done": 1, "size": 2, "unit": "lines"}"#).unwrap();
+        assert_eq!(deserialized.unit, Some(ProgressUnit::Other));
+    }
+
     #[test]
     fn test_error_info() {
         let error = PyRunnerError::task_execution_failed("测试错误");
@@ -126,4 +491,118 @@ mod tests {
         assert_eq!(deserialized.pages, 10);
         assert_eq!(deserialized.words, 5000);
     }
+
+    #[test]
+    fn test_output_info() {
+        let output = OutputMessage::new(
+            serde_json::json!({"text": "你好", "confidence": 0.98}),
+            vec!["/tmp/out.txt".into()],
+        );
+        let message = Message::Output(output.clone());
+
+        let serialized = serde_json::to_string(&message).unwrap();
+        println!("Message(Output(OutputMessage)) serialized: {serialized}");
+        let deserialized: Message = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, message);
+
+        let encoded = bincode::serialize(&message).unwrap();
+        let decoded: Message = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded, message);
+
+        let deserialized: OutputMessage = serde_json::from_str("{}").unwrap();
+        assert_eq!(deserialized.value, serde_json::Value::Null);
+        assert!(deserialized.artifacts.is_empty());
+    }
+
+    #[test]
+    fn test_hello() {
+        let hello = HelloMessage::new(PROTOCOL_VERSION)
+            .with_script("convert.py")
+            .with_capability("Output");
+        let message = Message::Hello(hello.clone());
+        let serialized = serde_json::to_string(&message).unwrap();
+        println!("Message(Hello(HelloMessage)) serialized: {serialized}");
+        assert_eq!(Message::parse(&serialized).unwrap(), Some(message.clone()));
+        assert!(hello.supports("Output"));
+        assert!(hello.check_version().is_ok());
+
+        let encoded = bincode::serialize(&message).unwrap();
+        let decoded: Message = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded, message);
+
+        let error = HelloMessage::new(PROTOCOL_VERSION + 1)
+            .check_version()
+            .unwrap_err();
+        assert!(matches!(error, PyRunnerError::ConfigError { .. }));
+        assert!(HelloMessage::new(0).check_version().is_err());
+    }
+
+    #[test]
+    fn test_command() {
+        assert_eq!(
+            serde_json::to_string(&Command::Pause).unwrap(),
+            r#""Pause""#
+        );
+
+        let command = Command::Request(RequestMessage::new(
+            1,
+            serde_json::json!({"kind": "password"}),
+        ));
+        let serialized = serde_json::to_string(&command).unwrap();
+        println!("Command(Request(RequestMessage)) serialized: {serialized}");
+        let deserialized: Command = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, command);
+
+        let message = Message::parse(r#"{"Response": {"id": 1, "error": "no handler"}}"#)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            message,
+            Message::Response(
+                ResponseMessage::new(1, serde_json::Value::Null).with_error("no handler")
+            )
+        );
+        let encoded = bincode::serialize(&message).unwrap();
+        let decoded: Message = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded, message);
+    }
+
+    #[test]
+    fn test_needs_input() {
+        let message = Message::parse(
+            r#"{"NeedsInput": {"id": 3, "kind": "password", "prompt": "请输入密码", "attempt": 2}}"#,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            message,
+            Message::NeedsInput(
+                NeedsInputMessage::new(3, InputKind::Password, "请输入密码").with_attempt(2)
+            )
+        );
+        let encoded = bincode::serialize(&message).unwrap();
+        let decoded: Message = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded, message);
+
+        let command = Command::Input(InputMessage { id: 3, value: None });
+        assert_eq!(
+            serde_json::to_string(&command).unwrap(),
+            r#"{"Input":{"id":3,"value":null}}"#
+        );
+    }
+
+    #[test]
+    fn test_parse_unknown_kind() {
+        assert_eq!(
+            Message::parse(r#"{"Heartbeat": {"seq": 1}}"#).unwrap(),
+            None
+        );
+        assert_eq!(
+            Message::parse(r#"{"Progress": {"done": 1, "size": 2, "eta": 3}}"#).unwrap(),
+            Some(Message::Progress(ProgressMessage::new(1, 2)))
+        );
+        // 已知类型的内容有误仍然是解析错误
+        assert!(Message::parse(r#"{"Progress": {"done": "x"}}"#).is_err());
+        assert!(Message::parse(r#"{"a": 1, "b": 2}"#).is_err());
+    }
 }
@@ -6,7 +6,11 @@ mod sender;
 #[allow(unused_imports)]
 pub use channel::create_message_channel;
 #[allow(unused_imports)]
-pub use message::{ErrorMessage, Message, ProgressMessage, ResultMessage};
+pub use message::{
+    Command, ErrorMessage, HelloMessage, InputKind, InputMessage, MIN_PROTOCOL_VERSION, Message,
+    NeedsInputMessage, OutputMessage, PROTOCOL_VERSION, ProgressMessage, ProgressUnit,
+    RequestMessage, ResponseMessage, ResultMessage, RetryMessage,
+};
 #[allow(unused_imports)]
 pub use receiver::MessageReceiver;
 #[allow(unused_imports)]
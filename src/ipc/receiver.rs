@@ -3,8 +3,8 @@ use crate::listener::MessageListener;
 use ipc_channel::ipc::{IpcError, IpcReceiver, TryRecvError};
 use std::sync::Mutex;
 use std::{sync::Arc, time::Duration};
+use tracing::instrument;
 use tracing::{error, info};
-use tracing::{instrument};
 
 pub struct MessageReceiver {
     receiver: IpcReceiver<Message>,
@@ -61,27 +61,9 @@ impl MessageReceiver {
                 Ok(message) => {
                     info!("{message:?}");
 
-                    match message {
-                        Message::Progress(progress) => {
-                            for listener in &self.listeners {
-                                if let Ok(mut l) = listener.lock() {
-                                    l.on_progress(progress);
-                                }
-                            }
-                        }
-                        Message::Error(e) => {
-                            for listener in &self.listeners {
-                                if let Ok(mut l) = listener.lock() {
-                                    l.on_error(e.clone());
-                                }
-                            }
-                        }
-                        Message::Result(result) => {
-                            for listener in &self.listeners {
-                                if let Ok(mut l) = listener.lock() {
-                                    l.on_result(result);
-                                }
-                            }
+                    for listener in &self.listeners {
+                        if let Ok(mut l) = listener.lock() {
+                            l.dispatch(message.clone());
                         }
                     }
                 }
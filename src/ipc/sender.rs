@@ -1,5 +1,10 @@
-use super::message::{ErrorMessage, Message, ProgressMessage, ResultMessage};
+use super::message::{
+    ErrorMessage, HelloMessage, Message, NeedsInputMessage, OutputMessage, ProgressMessage,
+    ResponseMessage, ResultMessage, RetryMessage,
+};
+use crate::control::InputResponder;
 use crate::error::PyRunnerError;
+use crate::listener::MessageListener;
 use ipc_channel::ipc::IpcSender;
 use tracing::{debug, error};
 
@@ -42,6 +47,22 @@ impl MessageSender {
         self.send_safe(Message::Result(result_info));
     }
 
+    pub fn send_output_safe(&self, output: OutputMessage) {
+        self.send_safe(Message::Output(output));
+    }
+
+    pub fn send_hello_safe(&self, hello: HelloMessage) {
+        self.send_safe(Message::Hello(hello));
+    }
+
+    pub fn send_response_safe(&self, response: ResponseMessage) {
+        self.send_safe(Message::Response(response));
+    }
+
+    pub fn send_retry_safe(&self, retry: RetryMessage) {
+        self.send_safe(Message::Retry(retry));
+    }
+
     #[allow(dead_code)]
     pub fn send_task_started(&self) {
         let progress = ProgressMessage::new(0, 0);
@@ -53,9 +74,9 @@ impl MessageSender {
         self.send_progress_safe(progress);
     }
 
-    pub fn send_task_completed(&self) {
-        let result = ResultMessage::new(100, 5000);
-        self.send_result_safe(result);
+    pub fn send_task_completed(&self, value: serde_json::Value, artifacts: Vec<String>) {
+        let output = OutputMessage::new(value, artifacts);
+        self.send_output_safe(output);
     }
 
     pub fn send_task_error_msg(&self, error_msg: String) {
@@ -73,3 +94,39 @@ impl MessageSender {
         self.sender.clone()
     }
 }
+
+/// 将执行器产生的消息转发到 IPC 通道，由另一端的 `MessageReceiver` 分发给监听器
+impl MessageListener for MessageSender {
+    fn on_progress(&mut self, progress: ProgressMessage) {
+        self.send_progress_safe(progress);
+    }
+
+    fn on_error(&mut self, error: ErrorMessage) {
+        self.send_error_safe(error);
+    }
+
+    fn on_result(&mut self, result: ResultMessage) {
+        self.send_result_safe(result);
+    }
+
+    fn on_output(&mut self, output: OutputMessage) {
+        self.send_output_safe(output);
+    }
+
+    fn on_hello(&mut self, hello: HelloMessage) {
+        self.send_hello_safe(hello);
+    }
+
+    fn on_response(&mut self, response: ResponseMessage) {
+        self.send_response_safe(response);
+    }
+
+    fn on_retry(&mut self, retry: RetryMessage) {
+        self.send_retry_safe(retry);
+    }
+
+    /// 应答器无法经过 IPC 传递，另一端需要通过执行器的 `ControlHandle` 应答
+    fn on_needs_input(&mut self, request: NeedsInputMessage, _responder: InputResponder) {
+        self.send_safe(Message::NeedsInput(request));
+    }
+}
@@ -1,14 +1,86 @@
-use crate::{error::Result, ipc::MessageSender};
-use jni::JNIEnv;
-use jni::objects::JClass;
+use crate::control::{ControlHandle, InputResponder};
+use crate::error::{PyRunnerError, Result};
+use crate::executor::TaskExecutor;
+use crate::ipc::{
+    ErrorMessage, InputKind, MessageReceiver, MessageSender, NeedsInputMessage, OutputMessage,
+    ProgressMessage, ResultMessage,
+};
+use crate::listener::{MessageListener, TracingListener};
+use crate::registry::{TaskRegistry, TaskStatus};
+use crate::throttle::ThrottledListener;
+use jni::objects::{GlobalRef, JClass, JObject, JString, JValue};
 #[allow(unused_imports)]
-use jni::sys::{jboolean, jfloat, jint, jstring};
+use jni::sys::{jboolean, jfloat, jint, jlong, jstring};
+use jni::{JNIEnv, JavaVM};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
 #[allow(unused_imports)]
 use tracing::{debug, error, info, trace, warn};
 
 #[allow(dead_code)]
 const TAG: &str = "libpr";
 
+/// Python 解释器路径，未设置时使用 `python3`
+const PYTHON_ENV: &str = "PYRUNNER_PYTHON";
+/// pdf2wps 转换脚本路径
+const PDF2WPS_SCRIPT_ENV: &str = "PYRUNNER_PDF2WPS_SCRIPT";
+/// raw2wps 转换脚本路径
+const RAW2WPS_SCRIPT_ENV: &str = "PYRUNNER_RAW2WPS_SCRIPT";
+/// PDF 密码通过环境变量传给子进程，避免出现在进程命令行中
+const PDF_PASSWORD_ENV: &str = "PYRUNNER_PDF_PASSWORD";
+
+/// `status()` 的返回值
+const STATUS_UNKNOWN: jint = -1;
+const STATUS_RUNNING: jint = 0;
+const STATUS_SUCCEEDED: jint = 1;
+const STATUS_FAILED: jint = 2;
+const STATUS_CANCELLED: jint = 3;
+
+/// `onNeedsInput` 的 `kind` 取值
+const INPUT_KIND_PASSWORD: jint = 0;
+const INPUT_KIND_TEXT: jint = 1;
+const INPUT_KIND_OTHER: jint = 2;
+
+static NEXT_TASK_ID: AtomicU64 = AtomicU64::new(1);
+
+fn next_task_id() -> u64 {
+    NEXT_TASK_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// 进程级的任务注册表，异步提交的任务都运行在它持有的 Tokio 运行时上
+fn registry() -> &'static TaskRegistry {
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    static REGISTRY: OnceLock<TaskRegistry> = OnceLock::new();
+
+    REGISTRY.get_or_init(|| {
+        let runtime = RUNTIME.get_or_init(|| {
+            tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .thread_name("pyrunner-worker")
+                .build()
+                .expect("Failed to create Tokio runtime")
+        });
+        TaskRegistry::new(runtime.handle().clone())
+    })
+}
+
+/// 同步执行中的任务的控制句柄，异步任务的控制句柄由注册表持有
+fn sync_controls() -> &'static Mutex<HashMap<u64, ControlHandle>> {
+    static CONTROLS: OnceLock<Mutex<HashMap<u64, ControlHandle>>> = OnceLock::new();
+    CONTROLS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn task_control(task_id: u64) -> Option<ControlHandle> {
+    let control = sync_controls()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&task_id)
+        .cloned();
+    control.or_else(|| registry().control(task_id))
+}
+
 fn init_logger() {
     #[cfg(target_os = "android")]
     {
@@ -130,12 +202,11 @@ pub extern "system" fn Java_com_example_TypeConverter_convertBool(
 pub extern "system" fn Java_com_example_TypeConverter_processString(
     mut env: JNIEnv,
     _class: JClass,
-    input: jstring,
+    input: JString,
 ) -> jstring {
     init_logger();
 
-    let java_string = unsafe { jni::objects::JString::from_raw(input) };
-    let input_string: String = match env.get_string(&java_string) {
+    let input_string: String = match env.get_string(&input) {
         Ok(java_str) => java_str.into(),
         Err(e) => {
             error!("Failed to get string from Java: {:?}", e);
@@ -162,39 +233,482 @@ fn jstring_to_string(env: &mut JNIEnv, jstring: jstring) -> Result<String> {
         .into())
 }
 
-#[allow(dead_code)]
-fn run_python_pdf2wps(_sender: MessageSender, _task_id: u64) {}
+/// 将任务消息回调到 Java 监听对象的 `MessageListener`
+///
+/// Java 侧需要实现以下方法：
+/// - `void onProgress(long done, long size)`
+/// - `void onError(int code, String msg)`
+/// - `void onResult(long pages, long words)`
+/// - `void onOutput(String json, String[] artifacts)`：通用结果，`json` 为 JSON 文本
+/// - `void onComplete(int code)`：仅异步提交的任务会回调，`code` 为 0 表示成功
+/// - `void onNeedsInput(long taskId, long requestId, int kind, String prompt, int attempt)`：
+///   脚本需要用户输入，`kind` 取值见 `INPUT_KIND_*`，通过 `ConvertCore.provideInput` 应答
+pub struct JavaListener {
+    vm: JavaVM,
+    listener: GlobalRef,
+    task_id: u64,
+    error: Option<PyRunnerError>,
+}
+
+impl JavaListener {
+    pub fn new(env: &mut JNIEnv, listener: &JObject, task_id: u64) -> Result<Self> {
+        Ok(Self {
+            vm: env.get_java_vm()?,
+            listener: env.new_global_ref(listener)?,
+            task_id,
+            error: None,
+        })
+    }
+
+    /// 取出回调过程中发生的第一个错误，例如 Java 侧抛出的异常
+    pub fn take_error(&mut self) -> Option<PyRunnerError> {
+        self.error.take()
+    }
+
+    /// 回调失败时记录错误并返回 false
+    fn call<F>(&mut self, name: &str, f: F) -> bool
+    where
+        F: FnOnce(&mut JNIEnv, &JObject) -> Result<()>,
+    {
+        match self.try_call(f) {
+            Ok(()) => true,
+            Err(e) => {
+                error!("回调Java方法{name}失败: {e:?}");
+                self.error.get_or_insert(e);
+                false
+            }
+        }
+    }
+
+    fn try_call<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce(&mut JNIEnv, &JObject) -> Result<()>,
+    {
+        // 回调发生在监听线程上，永久附加可避免每条消息都重复附加/分离
+        let mut env = self.vm.attach_current_thread_permanently()?;
+        // 附加的线程没有 Java 栈帧，局部引用需要显式释放
+        env.with_local_frame(8, |env| {
+            let result = f(env, self.listener.as_obj());
+            Self::check_exception(env)?;
+            result
+        })
+    }
+
+    fn check_exception(env: &mut JNIEnv) -> Result<()> {
+        if env.exception_check()? {
+            env.exception_describe()?;
+            env.exception_clear()?;
+            return Err(PyRunnerError::JniError(jni::errors::Error::JavaException));
+        }
+        Ok(())
+    }
+}
+
+impl MessageListener for JavaListener {
+    fn on_progress(&mut self, progress: ProgressMessage) {
+        self.call("onProgress", |env, listener| {
+            env.call_method(
+                listener,
+                "onProgress",
+                "(JJ)V",
+                &[
+                    JValue::Long(progress.done as jlong),
+                    JValue::Long(progress.size as jlong),
+                ],
+            )?;
+            Ok(())
+        });
+    }
+
+    fn on_error(&mut self, error: ErrorMessage) {
+        self.call("onError", |env, listener| {
+            let message = env.new_string(&error.error_message)?;
+            env.call_method(
+                listener,
+                "onError",
+                "(ILjava/lang/String;)V",
+                &[
+                    JValue::Int(error.error_code as jint),
+                    JValue::Object(&message),
+                ],
+            )?;
+            Ok(())
+        });
+    }
+
+    fn on_result(&mut self, result: ResultMessage) {
+        self.call("onResult", |env, listener| {
+            env.call_method(
+                listener,
+                "onResult",
+                "(JJ)V",
+                &[
+                    JValue::Long(result.pages as jlong),
+                    JValue::Long(result.words as jlong),
+                ],
+            )?;
+            Ok(())
+        });
+    }
+
+    fn on_output(&mut self, output: OutputMessage) {
+        self.call("onOutput", |env, listener| {
+            let json = env.new_string(output.value.to_string())?;
+            let artifacts = env.new_object_array(
+                output.artifacts.len() as jint,
+                "java/lang/String",
+                JObject::null(),
+            )?;
+            for (index, artifact) in output.artifacts.iter().enumerate() {
+                let artifact = env.new_string(artifact)?;
+                env.set_object_array_element(&artifacts, index as jint, &artifact)?;
+                env.delete_local_ref(artifact)?;
+            }
+            env.call_method(
+                listener,
+                "onOutput",
+                "(Ljava/lang/String;[Ljava/lang/String;)V",
+                &[JValue::Object(&json), JValue::Object(&artifacts)],
+            )?;
+            Ok(())
+        });
+    }
+
+    /// 应答器可能经过 IPC 转发而失效，Java 侧统一通过 `provideInput(taskId, requestId, value)` 应答
+    fn on_needs_input(&mut self, request: NeedsInputMessage, _responder: InputResponder) {
+        let task_id = self.task_id;
+        let kind = match request.kind {
+            InputKind::Password => INPUT_KIND_PASSWORD,
+            InputKind::Text => INPUT_KIND_TEXT,
+            InputKind::Other => INPUT_KIND_OTHER,
+        };
+        let called = self.call("onNeedsInput", |env, listener| {
+            let prompt = env.new_string(&request.prompt)?;
+            env.call_method(
+                listener,
+                "onNeedsInput",
+                "(JJILjava/lang/String;I)V",
+                &[
+                    JValue::Long(task_id as jlong),
+                    JValue::Long(request.id as jlong),
+                    JValue::Int(kind),
+                    JValue::Object(&prompt),
+                    JValue::Int(request.attempt as jint),
+                ],
+            )?;
+            Ok(())
+        });
+        // Java 回调失败时没有人会应答，放弃输入以免脚本一直等待
+        if !called && let Some(control) = task_control(task_id) {
+            control.provide_input(request.id, None);
+        }
+    }
+
+    fn on_complete(&mut self, error_code: i32) {
+        self.call("onComplete", |env, listener| {
+            env.call_method(
+                listener,
+                "onComplete",
+                "(I)V",
+                &[JValue::Int(error_code as jint)],
+            )?;
+            Ok(())
+        });
+    }
+}
+
+fn python_exec() -> String {
+    std::env::var(PYTHON_ENV).unwrap_or_else(|_| "python3".into())
+}
+
+/// 在当前线程上同步执行任务，消息经由 `sender` 转发给监听线程
+fn run_executor(executor: TaskExecutor, mut sender: MessageSender) -> Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(executor.execute(&mut sender))
+}
+
+fn pdf2wps_executor(
+    task_id: u64,
+    pdf_path: String,
+    pdf_password: String,
+    wps_path: String,
+) -> Result<TaskExecutor> {
+    let script = std::env::var(PDF2WPS_SCRIPT_ENV)?;
+    Ok(
+        TaskExecutor::new(python_exec(), vec![script, pdf_path, wps_path])
+            .with_task_id(task_id)
+            .with_env(PDF_PASSWORD_ENV, pdf_password),
+    )
+}
 
 #[allow(dead_code)]
-fn run_python_raw2wps(_sender: MessageSender, _task_id: u64) {}
+fn run_python_raw2wps(
+    sender: MessageSender,
+    task_id: u64,
+    raw_path: String,
+    wps_path: String,
+) -> Result<()> {
+    let script = std::env::var(RAW2WPS_SCRIPT_ENV)?;
+    let executor =
+        TaskExecutor::new(python_exec(), vec![script, raw_path, wps_path]).with_task_id(task_id);
+    run_executor(executor, sender)
+}
 
 fn pdf2wps(
     env: &mut JNIEnv,
     pdf_path: jstring,
     pdf_password: jstring,
     wps_path: jstring,
+    listener: &JObject,
 ) -> Result<()> {
-    let _pdf_path: String = jstring_to_string(env, pdf_path)?;
-    let _pdf_password: String = jstring_to_string(env, pdf_password)?;
-    let _wps_path: String = jstring_to_string(env, wps_path)?;
-    Ok(())
+    let pdf_path: String = jstring_to_string(env, pdf_path)?;
+    let pdf_password: String = jstring_to_string(env, pdf_password)?;
+    let wps_path: String = jstring_to_string(env, wps_path)?;
+
+    let task_id = next_task_id();
+    info!("pdf2wps: task_id: {task_id}, pdf_path: {pdf_path}, wps_path: {wps_path}");
+
+    let java_listener = if listener.is_null() {
+        None
+    } else {
+        Some(Arc::new(Mutex::new(ThrottledListener::new(
+            JavaListener::new(env, listener, task_id)?,
+        ))))
+    };
+    let listener: Arc<Mutex<dyn MessageListener + Send>> = match &java_listener {
+        Some(java_listener) => java_listener.clone(),
+        None => Arc::new(Mutex::new(TracingListener::new(task_id))),
+    };
+    // 接收端在监听线程中创建，只需要监听器本身可以跨线程
+    let (sender, receiver) = ipc_channel::ipc::channel()?;
+    let sender = MessageSender::new(sender);
+    let monitor = std::thread::spawn(move || {
+        MessageReceiver::new(receiver)
+            .with_listener(listener)
+            .start_listening()
+    });
+
+    // 只有 Java 监听器能够应答输入请求，此时才开启控制通道
+    let mut executor = pdf2wps_executor(task_id, pdf_path, pdf_password, wps_path)?;
+    if java_listener.is_some() {
+        executor = executor.with_control_channel();
+    }
+    if let Some(control) = executor.control_handle() {
+        sync_controls()
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(task_id, control);
+    }
+
+    // sender 在任务结束时被释放，监听线程随之退出
+    let result = run_executor(executor, sender);
+    sync_controls()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(&task_id);
+    monitor
+        .join()
+        .map_err(|_| PyRunnerError::internal_error("消息监听线程异常退出"))?;
+    result?;
+
+    // 转换本身成功时，再报告 Java 回调中出现的异常
+    let error = java_listener.and_then(|java_listener| {
+        let mut java_listener = java_listener.lock().ok()?;
+        java_listener.flush();
+        java_listener.get_mut().take_error()
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
 }
 
 #[unsafe(no_mangle)]
 pub extern "system" fn Java_androidx_appcompat_ConvertCore_pdf2wps(
-    mut _env: JNIEnv,
+    mut env: JNIEnv,
+    _class: JClass,
+    pdf_path: jstring,
+    pdf_password: jstring,
+    wps_path: jstring,
+) -> jint {
+    init_logger();
+
+    match pdf2wps(&mut env, pdf_path, pdf_password, wps_path, &JObject::null()) {
+        Ok(_) => 0 as jint,
+        Err(e) => {
+            error!("Failed to convert PDF to WPS: {:?}", e);
+            e.error_code() as jint
+        }
+    }
+}
+
+/// 与 `pdf2wps` 相同，但通过 `listener` 回调进度、错误和结果
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_androidx_appcompat_ConvertCore_pdf2wpsWithListener(
+    mut env: JNIEnv,
     _class: JClass,
     pdf_path: jstring,
     pdf_password: jstring,
     wps_path: jstring,
+    listener: JObject,
 ) -> jint {
     init_logger();
 
-    match pdf2wps(&mut _env, pdf_path, pdf_password, wps_path) {
+    match pdf2wps(&mut env, pdf_path, pdf_password, wps_path, &listener) {
         Ok(_) => 0 as jint,
         Err(e) => {
             error!("Failed to convert PDF to WPS: {:?}", e);
-            1 as jint
+            e.error_code() as jint
+        }
+    }
+}
+
+fn submit_pdf2wps(
+    env: &mut JNIEnv,
+    pdf_path: jstring,
+    pdf_password: jstring,
+    wps_path: jstring,
+    listener: &JObject,
+) -> Result<u64> {
+    let pdf_path: String = jstring_to_string(env, pdf_path)?;
+    let pdf_password: String = jstring_to_string(env, pdf_password)?;
+    let wps_path: String = jstring_to_string(env, wps_path)?;
+
+    let task_id = next_task_id();
+    info!("submitPdf2wps: task_id: {task_id}, pdf_path: {pdf_path}, wps_path: {wps_path}");
+
+    let executor = pdf2wps_executor(task_id, pdf_path, pdf_password, wps_path)?;
+    let (executor, listener): (_, Box<dyn MessageListener + Send>) = if listener.is_null() {
+        (executor, Box::new(TracingListener::new(task_id)))
+    } else {
+        (
+            executor.with_control_channel(),
+            Box::new(ThrottledListener::new(JavaListener::new(
+                env, listener, task_id,
+            )?)),
+        )
+    };
+    Ok(registry().submit(executor, listener))
+}
+
+/// 异步提交 pdf2wps 任务，立即返回 task_id；失败时返回负的错误码
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_androidx_appcompat_ConvertCore_submitPdf2wps(
+    mut env: JNIEnv,
+    _class: JClass,
+    pdf_path: jstring,
+    pdf_password: jstring,
+    wps_path: jstring,
+    listener: JObject,
+) -> jlong {
+    init_logger();
+
+    match submit_pdf2wps(&mut env, pdf_path, pdf_password, wps_path, &listener) {
+        Ok(task_id) => task_id as jlong,
+        Err(e) => {
+            error!("Failed to submit pdf2wps task: {:?}", e);
+            -(e.error_code() as jlong)
+        }
+    }
+}
+
+/// 应答 `onNeedsInput`，`value` 为 null 表示用户放弃输入（任务随之取消）
+///
+/// 任务不存在或未开启控制通道时返回 false
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_androidx_appcompat_ConvertCore_provideInput(
+    mut env: JNIEnv,
+    _class: JClass,
+    task_id: jlong,
+    request_id: jlong,
+    value: JString,
+) -> jboolean {
+    init_logger();
+
+    info!("provideInput: task_id: {task_id}, request_id: {request_id}");
+    let value = if value.is_null() {
+        None
+    } else {
+        match env.get_string(&value) {
+            Ok(value) => Some(value.into()),
+            Err(e) => {
+                error!("Failed to get input from Java: {:?}", e);
+                return 0;
+            }
+        }
+    };
+    match task_control(task_id as u64) {
+        Some(control) => {
+            control.provide_input(request_id as u64, value);
+            1
+        }
+        None => {
+            warn!("provideInput: 任务不存在或未开启控制通道: {task_id}");
+            0
+        }
+    }
+}
+
+/// 请求取消任务，任务不存在时返回 false
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_androidx_appcompat_ConvertCore_cancel(
+    _env: JNIEnv,
+    _class: JClass,
+    task_id: jlong,
+) -> jboolean {
+    init_logger();
+
+    info!("cancel: task_id: {task_id}");
+    if registry().cancel(task_id as u64) {
+        1
+    } else {
+        0
+    }
+}
+
+/// 查询任务状态，取值见 `STATUS_*`
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_androidx_appcompat_ConvertCore_status(
+    _env: JNIEnv,
+    _class: JClass,
+    task_id: jlong,
+) -> jint {
+    init_logger();
+
+    match registry().status(task_id as u64) {
+        None => STATUS_UNKNOWN,
+        Some(TaskStatus::Queued | TaskStatus::Running | TaskStatus::Suspended) => STATUS_RUNNING,
+        Some(TaskStatus::Succeeded) => STATUS_SUCCEEDED,
+        Some(TaskStatus::Failed(_)) => STATUS_FAILED,
+        Some(TaskStatus::Cancelled) => STATUS_CANCELLED,
+    }
+}
+
+/// 等待任务结束并返回错误码（0 表示成功），`timeout_ms` 小于 0 时无限等待
+///
+/// 等待超时返回 `Timeout` 的错误码，任务仍可再次等待；任务结束后其 task_id 随即失效
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_androidx_appcompat_ConvertCore_await(
+    _env: JNIEnv,
+    _class: JClass,
+    task_id: jlong,
+    timeout_ms: jlong,
+) -> jint {
+    init_logger();
+
+    let timeout = u64::try_from(timeout_ms).ok().map(Duration::from_millis);
+    let result = registry().wait(task_id as u64, timeout).and_then(|status| {
+        status.error_code().ok_or_else(|| PyRunnerError::Timeout {
+            operation: format!("await task {task_id}"),
+        })
+    });
+    match result {
+        Ok(code) => code as jint,
+        Err(e) => {
+            warn!("await: task_id: {task_id}, {e}");
+            e.error_code() as jint
         }
     }
 }
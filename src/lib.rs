@@ -1 +1,20 @@
-
+pub mod async_listener;
+pub mod cancel;
+pub mod combinator;
+pub mod control;
+pub mod error;
+pub mod executor;
+pub mod ipc;
+pub mod jni;
+pub mod limits;
+pub mod listener;
+pub mod pool;
+pub mod process;
+pub mod python_client;
+pub mod registry;
+pub mod retry;
+pub mod stream;
+#[cfg(test)]
+mod test_util;
+pub mod throttle;
+pub mod traceback;
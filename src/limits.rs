@@ -0,0 +1,238 @@
+use crate::traceback::PythonTraceback;
+use nix::sys::resource::{Resource, getrlimit, rlim_t, setrlimit};
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+/// 子进程的资源限制，在子进程 exec 之前通过 `setrlimit` 设置
+///
+/// 未设置的项沿用父进程的限制；设置的值超过父进程的硬限制时按硬限制处理
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    memory: Option<u64>,
+    cpu_time: Option<Duration>,
+    open_files: Option<u64>,
+    file_size: Option<u64>,
+}
+
+impl ResourceLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 虚拟内存上限（字节），对应 `RLIMIT_AS`，超出后 Python 抛出 `MemoryError`
+    pub fn with_memory(mut self, bytes: u64) -> Self {
+        self.memory = Some(bytes);
+        self
+    }
+
+    /// CPU 时间上限，对应 `RLIMIT_CPU`，按秒向上取整，超出后子进程收到 SIGXCPU
+    pub fn with_cpu_time(mut self, cpu_time: Duration) -> Self {
+        self.cpu_time = Some(cpu_time);
+        self
+    }
+
+    /// 同时打开的文件数上限，对应 `RLIMIT_NOFILE`
+    pub fn with_open_files(mut self, count: u64) -> Self {
+        self.open_files = Some(count);
+        self
+    }
+
+    /// 单个文件大小上限（字节），对应 `RLIMIT_FSIZE`
+    pub fn with_file_size(mut self, bytes: u64) -> Self {
+        self.file_size = Some(bytes);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// 在子进程中设置限制，只调用异步信号安全的 getrlimit/setrlimit
+    pub(crate) fn apply(&self) -> std::io::Result<()> {
+        if let Some(bytes) = self.memory {
+            limit(Resource::RLIMIT_AS, bytes, bytes)?;
+        }
+        if let Some(seconds) = self.cpu_seconds() {
+            // 软限制发送 SIGXCPU；硬限制多留 1 秒，脚本忽略 SIGXCPU 时由内核 SIGKILL，
+            // `exhausted` 根据子进程消耗的 CPU 时间识别这种情况
+            limit(Resource::RLIMIT_CPU, seconds, seconds.saturating_add(1))?;
+        }
+        if let Some(count) = self.open_files {
+            limit(Resource::RLIMIT_NOFILE, count, count)?;
+        }
+        if let Some(bytes) = self.file_size {
+            limit(Resource::RLIMIT_FSIZE, bytes, bytes)?;
+        }
+        Ok(())
+    }
+
+    /// 根据子进程的退出状态、Python 异常和消耗的 CPU 时间判断是否因超出限制而失败，返回超出的限制
+    pub(crate) fn exhausted(
+        &self,
+        status: &ExitStatus,
+        traceback: Option<&PythonTraceback>,
+        cpu_time: Option<Duration>,
+    ) -> Option<String> {
+        let signal = status.signal();
+        // SIGKILL 也可能来自 OOM killer、Android 低内存查杀或外部 kill -9，只有 CPU 时间已用满软限制时
+        // 才算超限：此时脚本已收到并忽略了 SIGXCPU。utime/stime 按时钟滴答截断，不直接与硬限制比较
+        if let Some(seconds) = self.cpu_seconds()
+            && (signal == Some(libc::SIGXCPU)
+                || (signal == Some(libc::SIGKILL)
+                    && cpu_time.is_some_and(|cpu_time| cpu_time >= Duration::from_secs(seconds))))
+        {
+            return Some(format!("CPU时间(RLIMIT_CPU): {seconds}秒"));
+        }
+        if let Some(bytes) = self.file_size
+            && (signal == Some(libc::SIGXFSZ) || has_errno(traceback, libc::EFBIG))
+        {
+            return Some(format!("文件大小(RLIMIT_FSIZE): {bytes}字节"));
+        }
+        if let Some(count) = self.open_files
+            && has_errno(traceback, libc::EMFILE)
+        {
+            return Some(format!("打开文件数(RLIMIT_NOFILE): {count}"));
+        }
+        if let Some(bytes) = self.memory
+            && traceback.is_some_and(|tb| tb.exc_type.rsplit('.').next() == Some("MemoryError"))
+        {
+            return Some(format!("内存(RLIMIT_AS): {bytes}字节"));
+        }
+        None
+    }
+
+    /// 已退出、尚未回收的子进程消耗的 CPU 时间，未限制 CPU 时间时不读取
+    pub(crate) fn cpu_time_used(&self, pid: u32) -> Option<Duration> {
+        self.cpu_time?;
+        cpu_time(pid)
+    }
+
+    fn cpu_seconds(&self) -> Option<u64> {
+        self.cpu_time
+            .map(|cpu_time| cpu_time.as_secs() + u64::from(cpu_time.subsec_nanos() > 0))
+    }
+}
+
+fn limit(resource: Resource, soft: u64, hard: u64) -> std::io::Result<()> {
+    let (_, current_hard) = getrlimit(resource)?;
+    let hard = rlim_t::try_from(hard)
+        .unwrap_or(current_hard)
+        .min(current_hard);
+    let soft = rlim_t::try_from(soft).unwrap_or(hard).min(hard);
+    setrlimit(resource, soft, hard)?;
+    Ok(())
+}
+
+/// 从 `/proc/<pid>/stat` 读取进程的 utime 与 stime 之和，僵尸进程仍保留这两项
+fn cpu_time(pid: u32) -> Option<Duration> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // 进程名可能包含空格和括号，从最后一个 ')' 之后按字段切分，utime、stime 是其后的第 12、13 个字段
+    let (_, fields) = stat.rsplit_once(')')?;
+    let mut fields = fields.split_whitespace().skip(11);
+    let utime: u64 = fields.next()?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+    // SAFETY: sysconf 没有内存安全方面的前置条件
+    let ticks_per_second = u64::try_from(unsafe { libc::sysconf(libc::_SC_CLK_TCK) }).ok()?;
+    if ticks_per_second == 0 {
+        return None;
+    }
+    Some(Duration::from_secs_f64(
+        (utime + stime) as f64 / ticks_per_second as f64,
+    ))
+}
+
+/// Python 的 `OSError` 消息以 `[Errno N]` 开头
+fn has_errno(traceback: Option<&PythonTraceback>, errno: i32) -> bool {
+    traceback.is_some_and(|tb| tb.message.starts_with(&format!("[Errno {errno}]")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::PyRunnerError;
+    use crate::executor::TaskExecutor;
+    use crate::test_util::NullListener;
+
+    async fn run(script: &str, limits: ResourceLimits) -> Result<(), PyRunnerError> {
+        TaskExecutor::new("python".into(), vec!["-c".into(), script.into()])
+            .with_resource_limits(limits)
+            .execute(&mut NullListener)
+            .await
+    }
+
+    fn resource(result: Result<(), PyRunnerError>) -> String {
+        match result {
+            Err(PyRunnerError::ResourceExhausted { resource }) => resource,
+            other => panic!("unexpected result: {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_and_cpu_limits() {
+        let limits = ResourceLimits::new().with_memory(256 << 20);
+        let result = run("data = bytearray(1 << 30)", limits).await;
+        assert!(resource(result).contains("RLIMIT_AS"));
+
+        let limits = ResourceLimits::new().with_cpu_time(Duration::from_secs(1));
+        let result = run("while True: pass", limits).await;
+        assert!(resource(result).contains("RLIMIT_CPU"));
+
+        let limits = ResourceLimits::new().with_memory(256 << 20);
+        run("data = bytearray(1 << 20)", limits).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_sigxcpu_ignored() {
+        // 忽略 SIGXCPU 的脚本在硬限制处被 SIGKILL
+        let script = "import signal\n\
+                      signal.signal(signal.SIGXCPU, signal.SIG_IGN)\n\
+                      while True: pass";
+        let limits = ResourceLimits::new().with_cpu_time(Duration::from_secs(1));
+        let result = run(script, limits).await;
+        assert!(resource(result).contains("RLIMIT_CPU"));
+    }
+
+    #[tokio::test]
+    async fn test_external_kill_is_not_exhausted() {
+        let executor = TaskExecutor::new(
+            "python".into(),
+            vec!["-c".into(), "import time; time.sleep(30)".into()],
+        )
+        .with_resource_limits(ResourceLimits::new().with_cpu_time(Duration::from_secs(1)));
+        let process = executor.process_handle();
+        tokio::spawn(async move {
+            let pid = loop {
+                match process.pid() {
+                    Some(pid) => break pid,
+                    None => tokio::time::sleep(Duration::from_millis(10)).await,
+                }
+            };
+            let pid = nix::unistd::Pid::from_raw(pid as i32);
+            nix::sys::signal::kill(pid, nix::sys::signal::Signal::SIGKILL).unwrap();
+        });
+
+        let result = executor.execute(&mut NullListener).await;
+        assert!(
+            matches!(result, Err(PyRunnerError::ProcessExecutionFailed { .. })),
+            "{result:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_limits() {
+        let script = "import tempfile\n\
+                      files = [tempfile.TemporaryFile() for _ in range(64)]";
+        let limits = ResourceLimits::new().with_open_files(32);
+        let result = run(script, limits).await;
+        assert!(resource(result).contains("RLIMIT_NOFILE"));
+
+        let script = "import tempfile\n\
+                      with tempfile.TemporaryFile() as f:\n    \
+                          f.write(b'x' * (2 << 20))\n    \
+                          f.flush()";
+        let limits = ResourceLimits::new().with_file_size(1 << 20);
+        let result = run(script, limits).await;
+        assert!(resource(result).contains("RLIMIT_FSIZE"));
+    }
+}
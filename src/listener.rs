@@ -1,41 +1,326 @@
-use tracing::{info, Span};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use indicatif::{HumanBytes, HumanDuration};
+use tracing::{Span, debug, error, info, warn};
 use tracing_indicatif::span_ext::IndicatifSpanExt as _;
+use tracing_indicatif::style::ProgressStyle;
+
+use crate::control::InputResponder;
+use crate::ipc::{
+    ErrorMessage, HelloMessage, Message, NeedsInputMessage, OutputMessage, ProgressMessage,
+    ProgressUnit, ResponseMessage, ResultMessage, RetryMessage,
+};
+
+/// 控制台进度条模板
+pub const PROGRESS_BAR_TEMPLATE: &str =
+    "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}";
+
+/// 总量未知时使用的转圈模板
+pub const PROGRESS_SPINNER_TEMPLATE: &str = "[{elapsed_precise}] {spinner:.cyan} {pos:>7} {msg}";
+
+/// 子进程的输出流
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl std::fmt::Display for OutputStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Stdout => write!(f, "stdout"),
+            Self::Stderr => write!(f, "stderr"),
+        }
+    }
+}
 
-use crate::ipc::{ErrorMessage, Message, ProgressMessage, ResultMessage};
+/// 以 `{` 开头的行被视为协议消息，解析失败说明脚本输出的消息格式有误
+pub fn looks_like_message(line: &str) -> bool {
+    line.trim_start().starts_with('{')
+}
 
+/// 任务消息的监听器
+///
+/// 子进程的输出由执行器解析，协议消息经 `dispatch` 按类型回调，其余行交给 `on_text`/`on_parse_error`
 pub trait MessageListener {
-    fn on_message(&mut self, message: String) {
-        info!("on_message: {message}");
-        if let Ok(message) = serde_json::from_str(&message) {
-            match message {
-                Message::Progress(progress) => self.on_progress(progress),
-                Message::Error(error) => self.on_error(error),
-                Message::Result(result) => self.on_result(result),
+    fn dispatch(&mut self, message: Message) {
+        match message {
+            Message::Progress(progress) => self.on_progress(progress),
+            Message::Error(error) => self.on_error(error),
+            Message::Result(result) => self.on_result(result),
+            Message::Output(output) => self.on_output(output),
+            Message::Hello(hello) => self.on_hello(hello),
+            Message::Response(response) => self.on_response(response),
+            Message::NeedsInput(request) => {
+                let responder = InputResponder::new(request.id, None);
+                self.on_needs_input(request, responder)
             }
+            Message::Retry(retry) => self.on_retry(retry),
         }
     }
     fn on_progress(&mut self, progress: ProgressMessage);
     fn on_error(&mut self, error: ErrorMessage);
     fn on_result(&mut self, result: ResultMessage);
+    /// 通用结果，默认只记录日志
+    fn on_output(&mut self, output: OutputMessage) {
+        info!(
+            "任务输出: {}, artifacts: {:?}",
+            output.value, output.artifacts
+        );
+    }
+    /// 子进程声明的协议版本和能力，默认只记录日志
+    fn on_hello(&mut self, hello: HelloMessage) {
+        info!(
+            "子进程握手: protocol_version: {}, script: {:?}, capabilities: {:?}",
+            hello.protocol_version, hello.script, hello.capabilities
+        );
+    }
+    /// 脚本对控制通道请求的应答，等待中的 `ControlHandle::request` 会同时收到
+    fn on_response(&mut self, response: ResponseMessage) {
+        debug!("收到应答: id: {}, error: {:?}", response.id, response.error);
+    }
+    /// 脚本需要用户输入，通过 `responder` 应答，可以保存后在其他线程异步应答
+    ///
+    /// 默认放弃输入，脚本会随之取消任务
+    fn on_needs_input(&mut self, request: NeedsInputMessage, responder: InputResponder) {
+        warn!(
+            "监听器未处理输入请求，放弃输入: kind: {:?}, prompt: {}",
+            request.kind, request.prompt
+        );
+        responder.decline();
+    }
+    /// 任务失败后即将按重试策略重新执行，默认只记录日志
+    fn on_retry(&mut self, retry: RetryMessage) {
+        warn!(
+            "任务将在{}ms后重试: 第{}/{}次, 错误码: {}, {}",
+            retry.delay_ms,
+            retry.attempt,
+            retry.max_attempts,
+            retry.error_code,
+            retry.error_message
+        );
+    }
+    /// 子进程输出到 stderr 的每一行
+    fn on_stderr(&mut self, line: String) {
+        self.on_text(line, OutputStream::Stderr);
+    }
+    /// 子进程输出的普通文本行（非协议消息）
+    fn on_text(&mut self, line: String, stream: OutputStream) {
+        match stream {
+            OutputStream::Stdout => info!("[{stream}] {line}"),
+            OutputStream::Stderr => warn!("[{stream}] {line}"),
+        }
+    }
+    /// 形似协议消息但无法解析的行，通常是脚本的协议实现有误
+    fn on_parse_error(&mut self, line: String, error: serde_json::Error) {
+        warn!("消息解析失败: {error}, line: {line}");
+    }
+    /// 任务结束时调用，`error_code` 为 0 表示成功
+    fn on_complete(&mut self, _error_code: i32) {}
+}
+
+impl<L: MessageListener + ?Sized> MessageListener for Box<L> {
+    fn dispatch(&mut self, message: Message) {
+        (**self).dispatch(message)
+    }
+    fn on_progress(&mut self, progress: ProgressMessage) {
+        (**self).on_progress(progress)
+    }
+    fn on_error(&mut self, error: ErrorMessage) {
+        (**self).on_error(error)
+    }
+    fn on_result(&mut self, result: ResultMessage) {
+        (**self).on_result(result)
+    }
+    fn on_output(&mut self, output: OutputMessage) {
+        (**self).on_output(output)
+    }
+    fn on_hello(&mut self, hello: HelloMessage) {
+        (**self).on_hello(hello)
+    }
+    fn on_response(&mut self, response: ResponseMessage) {
+        (**self).on_response(response)
+    }
+    fn on_needs_input(&mut self, request: NeedsInputMessage, responder: InputResponder) {
+        (**self).on_needs_input(request, responder)
+    }
+    fn on_retry(&mut self, retry: RetryMessage) {
+        (**self).on_retry(retry)
+    }
+    fn on_stderr(&mut self, line: String) {
+        (**self).on_stderr(line)
+    }
+    fn on_text(&mut self, line: String, stream: OutputStream) {
+        (**self).on_text(line, stream)
+    }
+    fn on_parse_error(&mut self, line: String, error: serde_json::Error) {
+        (**self).on_parse_error(line, error)
+    }
+    fn on_complete(&mut self, error_code: i32) {
+        (**self).on_complete(error_code)
+    }
+}
+
+/// 共享的监听器，`MessageReceiver` 持有的 `Arc<Mutex<dyn MessageListener>>` 也可以直接交给执行器
+impl<L: MessageListener + ?Sized> MessageListener for Arc<Mutex<L>> {
+    fn dispatch(&mut self, message: Message) {
+        lock(self).dispatch(message)
+    }
+    fn on_progress(&mut self, progress: ProgressMessage) {
+        lock(self).on_progress(progress)
+    }
+    fn on_error(&mut self, error: ErrorMessage) {
+        lock(self).on_error(error)
+    }
+    fn on_result(&mut self, result: ResultMessage) {
+        lock(self).on_result(result)
+    }
+    fn on_output(&mut self, output: OutputMessage) {
+        lock(self).on_output(output)
+    }
+    fn on_hello(&mut self, hello: HelloMessage) {
+        lock(self).on_hello(hello)
+    }
+    fn on_response(&mut self, response: ResponseMessage) {
+        lock(self).on_response(response)
+    }
+    fn on_needs_input(&mut self, request: NeedsInputMessage, responder: InputResponder) {
+        lock(self).on_needs_input(request, responder)
+    }
+    fn on_retry(&mut self, retry: RetryMessage) {
+        lock(self).on_retry(retry)
+    }
+    fn on_stderr(&mut self, line: String) {
+        lock(self).on_stderr(line)
+    }
+    fn on_text(&mut self, line: String, stream: OutputStream) {
+        lock(self).on_text(line, stream)
+    }
+    fn on_parse_error(&mut self, line: String, error: serde_json::Error) {
+        lock(self).on_parse_error(line, error)
+    }
+    fn on_complete(&mut self, error_code: i32) {
+        lock(self).on_complete(error_code)
+    }
+}
+
+fn lock<L: ?Sized>(listener: &Mutex<L>) -> MutexGuard<'_, L> {
+    listener.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// 根据进度更新估算吞吐量和剩余时间，阶段切换或进度回退时重新开始估算
+#[derive(Debug, Default)]
+pub struct ProgressRate {
+    origin: Option<(Instant, u64)>,
+    stage: Option<String>,
+}
+
+impl ProgressRate {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 返回每秒完成量和预计剩余时间，数据不足时返回 `None`
+    pub fn update(&mut self, progress: &ProgressMessage) -> Option<(f64, Option<Duration>)> {
+        self.update_at(progress, Instant::now())
+    }
+
+    fn update_at(
+        &mut self,
+        progress: &ProgressMessage,
+        now: Instant,
+    ) -> Option<(f64, Option<Duration>)> {
+        match self.origin {
+            Some((_, origin_done))
+                if self.stage == progress.stage && progress.done >= origin_done => {}
+            _ => {
+                self.origin = Some((now, progress.done));
+                self.stage = progress.stage.clone();
+                return None;
+            }
+        }
+
+        let (start, start_done) = self.origin?;
+        let elapsed = now.duration_since(start).as_secs_f64();
+        if elapsed <= 0.0 || progress.done == start_done {
+            return None;
+        }
+        let rate = (progress.done - start_done) as f64 / elapsed;
+        let eta = progress.fraction().map(|_| {
+            Duration::from_secs_f64(progress.size.saturating_sub(progress.done) as f64 / rate)
+        });
+        Some((rate, eta))
+    }
+}
+
+fn format_rate(rate: f64, unit: Option<ProgressUnit>) -> String {
+    match unit {
+        Some(ProgressUnit::Bytes) => format!("{}/s", HumanBytes(rate as u64)),
+        Some(ProgressUnit::Pages) => format!("{rate:.1} 页/s"),
+        Some(ProgressUnit::Items) => format!("{rate:.1} 项/s"),
+        Some(ProgressUnit::Other) | None => format!("{rate:.1}/s"),
+    }
 }
 
 pub struct ConsoleProgressListener {
     span: Span,
+    task_id: u64,
+    rate: ProgressRate,
+    indeterminate: bool,
 }
 
 impl ConsoleProgressListener {
     pub fn new(task_id: u64, span: Span) -> Self {
         span.pb_set_message(&format!("task_id: {task_id}"));
-        Self { span }
+        Self {
+            span,
+            task_id,
+            rate: ProgressRate::new(),
+            indeterminate: false,
+        }
+    }
+
+    fn set_indeterminate(&mut self, indeterminate: bool) {
+        if self.indeterminate == indeterminate {
+            return;
+        }
+        self.indeterminate = indeterminate;
+        let template = if indeterminate {
+            PROGRESS_SPINNER_TEMPLATE
+        } else {
+            PROGRESS_BAR_TEMPLATE
+        };
+        self.span
+            .pb_set_style(&ProgressStyle::with_template(template).unwrap());
     }
 }
 
 impl MessageListener for ConsoleProgressListener {
     fn on_progress(&mut self, progress: ProgressMessage) {
-        if progress.size > 0 {
+        self.set_indeterminate(progress.indeterminate);
+        if progress.size > 0 && !progress.indeterminate {
             self.span.pb_set_length(progress.size);
         }
         self.span.pb_set_position(progress.done);
+
+        let mut parts = vec![format!("task_id: {}", self.task_id)];
+        if let Some(stage) = &progress.stage {
+            parts.push(match (progress.stage_index, progress.stage_count) {
+                (Some(index), Some(count)) => format!("[{index}/{count}] {stage}"),
+                _ => stage.clone(),
+            });
+        }
+        if let Some(message) = &progress.message {
+            parts.push(message.clone());
+        }
+        if let Some((rate, eta)) = self.rate.update(&progress) {
+            parts.push(format_rate(rate, progress.unit));
+            if let Some(eta) = eta {
+                parts.push(format!("剩余 {}", HumanDuration(eta)));
+            }
+        }
+        self.span.pb_set_message(&parts.join(" | "));
     }
 
     fn on_error(&mut self, error: ErrorMessage) {
@@ -49,4 +334,89 @@ impl MessageListener for ConsoleProgressListener {
             result.pages, result.words
         ));
     }
+
+    fn on_output(&mut self, output: OutputMessage) {
+        self.span
+            .pb_set_finish_message(&format!("✅ 任务完成: {}", output.value));
+    }
+}
+
+/// 将消息写入 tracing 日志的监听器，用于没有界面可以展示进度的场景
+pub struct TracingListener {
+    task_id: u64,
+}
+
+impl TracingListener {
+    pub fn new(task_id: u64) -> Self {
+        Self { task_id }
+    }
+}
+
+impl MessageListener for TracingListener {
+    fn on_progress(&mut self, progress: ProgressMessage) {
+        info!(
+            task_id = self.task_id,
+            stage = progress.stage,
+            "任务进度: {}/{} {}",
+            progress.done,
+            progress.size,
+            progress.message.unwrap_or_default()
+        );
+    }
+
+    fn on_error(&mut self, error: ErrorMessage) {
+        error!(
+            task_id = self.task_id,
+            "任务出错: [{}] {}", error.error_code, error.error_message
+        );
+    }
+
+    fn on_result(&mut self, result: ResultMessage) {
+        info!(
+            task_id = self.task_id,
+            "任务完成: {} 页，{} 字", result.pages, result.words
+        );
+    }
+
+    fn on_output(&mut self, output: OutputMessage) {
+        info!(
+            task_id = self.task_id,
+            "任务完成: {}, artifacts: {:?}", output.value, output.artifacts
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_rate() {
+        let start = Instant::now();
+        let mut rate = ProgressRate::new();
+        assert_eq!(rate.update_at(&ProgressMessage::new(0, 100), start), None);
+
+        let (per_sec, eta) = rate
+            .update_at(
+                &ProgressMessage::new(20, 100),
+                start + Duration::from_secs(2),
+            )
+            .unwrap();
+        assert_eq!(per_sec, 10.0);
+        assert_eq!(eta, Some(Duration::from_secs(8)));
+
+        let indeterminate = ProgressMessage::new(40, 0).with_indeterminate(true);
+        let (per_sec, eta) = rate
+            .update_at(&indeterminate, start + Duration::from_secs(4))
+            .unwrap();
+        assert_eq!(per_sec, 10.0);
+        assert_eq!(eta, None);
+
+        // 切换阶段后重新开始估算
+        let next_stage = ProgressMessage::new(0, 10).with_stage("排版", 2, 2);
+        assert_eq!(
+            rate.update_at(&next_stage, start + Duration::from_secs(5)),
+            None
+        );
+    }
 }
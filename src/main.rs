@@ -1,14 +1,8 @@
 use tracing::{Span, error, info, instrument};
 
-mod error;
-mod executor;
-mod ipc;
-mod jni;
-mod listener;
-
-use executor::TaskExecutor;
-
-use crate::listener::ConsoleProgressListener;
+use pr::executor::TaskExecutor;
+use pr::listener::{ConsoleProgressListener, PROGRESS_BAR_TEMPLATE};
+use pr::throttle::ThrottledListener;
 
 fn init_logger() {
     use tracing_indicatif::filter::IndicatifFilter;
@@ -18,12 +12,8 @@ fn init_logger() {
     };
 
     let env_filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
-    let indicatif_layer = tracing_indicatif::IndicatifLayer::new().with_progress_style(
-        ProgressStyle::with_template(
-            "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
-        )
-        .unwrap(),
-    );
+    let indicatif_layer = tracing_indicatif::IndicatifLayer::new()
+        .with_progress_style(ProgressStyle::with_template(PROGRESS_BAR_TEMPLATE).unwrap());
 
     tracing_subscriber::registry()
         .with(env_filter)
@@ -44,7 +34,8 @@ async fn demo_process_task() {
 
     let task_id = 2;
     let executor = TaskExecutor::new("python".into(), vec!["src/demo_progress.py".into()]);
-    let mut listener = ConsoleProgressListener::new(task_id, Span::current());
+    let mut listener =
+        ThrottledListener::new(ConsoleProgressListener::new(task_id, Span::current()));
 
     match executor.execute(&mut listener).await {
         Ok(_) => info!("✅ 任务执行成功"),
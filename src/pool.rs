@@ -0,0 +1,777 @@
+use crate::cancel::CancelHandle;
+use crate::control::ControlHandle;
+use crate::error::{PyRunnerError, Result};
+use crate::executor::TaskExecutor;
+use crate::listener::MessageListener;
+use crate::process::ProcessHandle;
+use crate::registry::{RecentFinished, TaskStatus};
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+use tracing::{info, warn};
+
+/// 用户触发的交互式任务
+pub const PRIORITY_INTERACTIVE: i32 = 100;
+
+/// `submit` 使用的默认优先级
+pub const PRIORITY_NORMAL: i32 = 0;
+
+/// 后台批量任务
+pub const PRIORITY_BACKGROUND: i32 = -100;
+
+/// 无法立即挂起（子进程尚未启动）时重试抢占的间隔
+const PREEMPT_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// 排队任务的出队顺序
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueueOrder {
+    /// 按提交顺序
+    #[default]
+    Fifo,
+    /// 优先级高的先出队，相同优先级按提交顺序
+    Priority,
+}
+
+struct Pending {
+    key: (i32, Reverse<u64>),
+    executor: TaskExecutor,
+    listener: Box<dyn MessageListener + Send>,
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+struct PoolTask {
+    cancel: CancelHandle,
+    control: Option<ControlHandle>,
+    process: ProcessHandle,
+    key: (i32, Reverse<u64>),
+    status: TaskStatus,
+}
+
+struct PoolState {
+    max_concurrency: usize,
+    order: QueueOrder,
+    preemption: bool,
+    queue: BinaryHeap<Pending>,
+    running: usize,
+    /// 被抢占而挂起的任务，不占用并发数
+    suspended: Vec<u64>,
+    retry_pending: bool,
+    next_seq: u64,
+    next_task_id: u64,
+    /// 排队、运行或挂起中的任务
+    tasks: HashMap<u64, PoolTask>,
+    /// 已结束的任务，结束时从 `tasks` 移入
+    finished: RecentFinished,
+}
+
+struct PoolInner {
+    handle: Handle,
+    state: Mutex<PoolState>,
+    changed: Condvar,
+}
+
+/// 限制并发数的任务池：最多同时运行 `max_concurrency` 个子进程，其余任务排队
+///
+/// 每个任务的消息交给提交时的监听器，结束时调用 `listener.on_complete`
+pub struct TaskPool {
+    inner: Arc<PoolInner>,
+}
+
+impl TaskPool {
+    /// 默认并发数为 CPU 核数，按提交顺序排队
+    pub fn new(handle: Handle) -> Self {
+        let max_concurrency = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        Self {
+            inner: Arc::new(PoolInner {
+                handle,
+                state: Mutex::new(PoolState {
+                    max_concurrency,
+                    order: QueueOrder::default(),
+                    preemption: false,
+                    queue: BinaryHeap::new(),
+                    running: 0,
+                    suspended: Vec::new(),
+                    retry_pending: false,
+                    next_seq: 0,
+                    next_task_id: 1,
+                    tasks: HashMap::new(),
+                    finished: RecentFinished::default(),
+                }),
+                changed: Condvar::new(),
+            }),
+        }
+    }
+
+    pub fn with_max_concurrency(self, max_concurrency: usize) -> Self {
+        self.set_max_concurrency(max_concurrency);
+        self
+    }
+
+    pub fn with_queue_order(self, order: QueueOrder) -> Self {
+        self.inner.lock().order = order;
+        self
+    }
+
+    /// 开启抢占：没有空闲位置时，高优先级任务的到来会挂起（SIGSTOP）优先级更低的运行中任务，
+    /// 有空闲位置后再恢复（SIGCONT）
+    ///
+    /// 只在 `QueueOrder::Priority` 下生效。挂起期间不计空闲超时，但计入 `with_timeout` 的总时长
+    pub fn with_preemption(self, preemption: bool) -> Self {
+        self.inner.lock().preemption = preemption;
+        self
+    }
+
+    /// 调整并发数，调大时立即启动排队的任务，调小时不影响正在运行的任务
+    pub fn set_max_concurrency(&self, max_concurrency: usize) {
+        let mut state = self.inner.lock();
+        state.max_concurrency = max_concurrency.max(1);
+        self.inner.schedule(&mut state);
+    }
+
+    pub fn submit<L>(&self, executor: TaskExecutor, listener: L) -> Result<u64>
+    where
+        L: MessageListener + Send + 'static,
+    {
+        self.submit_with_priority(executor, listener, PRIORITY_NORMAL)
+    }
+
+    /// 提交任务后立即返回 task_id，`priority` 只在 `QueueOrder::Priority` 下生效
+    ///
+    /// 执行器未设置 task_id（为 0）时由任务池分配；设置的 task_id 与池中未结束的任务重复时返回
+    /// `InvalidParameter`，与已结束的任务重复时丢弃旧任务的状态
+    pub fn submit_with_priority<L>(
+        &self,
+        executor: TaskExecutor,
+        listener: L,
+        priority: i32,
+    ) -> Result<u64>
+    where
+        L: MessageListener + Send + 'static,
+    {
+        let mut state = self.inner.lock();
+        let task_id = match executor.task_id() {
+            0 => state.allocate_task_id(),
+            task_id if state.tasks.contains_key(&task_id) => {
+                return Err(PyRunnerError::InvalidParameter {
+                    parameter: "task_id".into(),
+                    value: format!("{task_id}已在任务池中"),
+                });
+            }
+            task_id => task_id,
+        };
+        let executor = executor.with_task_id(task_id);
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        let priority = match state.order {
+            QueueOrder::Fifo => PRIORITY_NORMAL,
+            QueueOrder::Priority => priority,
+        };
+        let key = (priority, Reverse(seq));
+
+        let task = PoolTask {
+            cancel: executor.cancel_handle(),
+            control: executor.control_handle(),
+            process: executor.process_handle(),
+            key,
+            status: TaskStatus::Queued,
+        };
+        state.finished.remove(task_id);
+        state.tasks.insert(task_id, task);
+        state.queue.push(Pending {
+            key,
+            executor,
+            listener: Box::new(listener),
+        });
+        self.inner.schedule(&mut state);
+        Ok(task_id)
+    }
+
+    /// 请求取消任务，排队中的任务直接出队；任务不存在时返回 false
+    pub fn cancel(&self, task_id: u64) -> bool {
+        let mut state = self.inner.lock();
+        let Some(task) = state.tasks.get_mut(&task_id) else {
+            return false;
+        };
+        task.cancel.cancel();
+        if task.status != TaskStatus::Queued {
+            return true;
+        }
+        state.finish(task_id, TaskStatus::Cancelled);
+
+        let mut cancelled = Vec::new();
+        state.queue = std::mem::take(&mut state.queue)
+            .into_iter()
+            .filter_map(|pending| {
+                if pending.executor.task_id() == task_id {
+                    cancelled.push(pending);
+                    None
+                } else {
+                    Some(pending)
+                }
+            })
+            .collect();
+        drop(state);
+        self.inner.changed.notify_all();
+
+        info!("排队中的任务已取消: task_id: {task_id}");
+        let error_code = TaskStatus::Cancelled.error_code().unwrap_or_default();
+        for mut pending in cancelled {
+            pending.listener.on_complete(error_code);
+        }
+        true
+    }
+
+    /// 任务的控制句柄，任务不存在或未开启控制通道时返回 `None`
+    pub fn control(&self, task_id: u64) -> Option<ControlHandle> {
+        self.inner.lock().tasks.get(&task_id)?.control.clone()
+    }
+
+    /// 任务的状态，已结束的任务只保留最近若干个
+    pub fn status(&self, task_id: u64) -> Option<TaskStatus> {
+        let state = self.inner.lock();
+        match state.tasks.get(&task_id) {
+            Some(task) => Some(task.status),
+            None => state.finished.get(task_id),
+        }
+    }
+
+    /// 排队中的任务数
+    pub fn queued(&self) -> usize {
+        self.inner.lock().queue.len()
+    }
+
+    /// 正在运行的任务数
+    pub fn running(&self) -> usize {
+        self.inner.lock().running
+    }
+
+    /// 阻塞等待任务结束，`timeout` 为 `None` 时无限等待
+    ///
+    /// 超时返回任务当前的状态；任务结束后移除它的状态
+    pub fn wait(&self, task_id: u64, timeout: Option<Duration>) -> Result<TaskStatus> {
+        let mut state = self
+            .inner
+            .wait_while(timeout, |state| state.tasks.contains_key(&task_id));
+        if let Some(task) = state.tasks.get(&task_id) {
+            return Ok(task.status);
+        }
+        state
+            .finished
+            .remove(task_id)
+            .ok_or_else(|| PyRunnerError::InvalidParameter {
+                parameter: "task_id".into(),
+                value: task_id.to_string(),
+            })
+    }
+
+    /// 阻塞等待所有任务结束，返回最近结束的任务的状态并将其移除
+    ///
+    /// 超时时仍在排队或运行的任务保留在任务池中
+    pub fn wait_all(&self, timeout: Option<Duration>) -> HashMap<u64, TaskStatus> {
+        let mut state = self.inner.wait_while(timeout, |state| {
+            state.running > 0 || !state.queue.is_empty() || !state.suspended.is_empty()
+        });
+        state.finished.take_all()
+    }
+}
+
+impl PoolState {
+    fn finish(&mut self, task_id: u64, status: TaskStatus) {
+        self.tasks.remove(&task_id);
+        self.finished.push(task_id, status);
+    }
+
+    /// 下一个未被占用的 task_id
+    fn allocate_task_id(&mut self) -> u64 {
+        loop {
+            let task_id = self.next_task_id;
+            self.next_task_id = self.next_task_id.wrapping_add(1).max(1);
+            if !self.tasks.contains_key(&task_id) {
+                return task_id;
+            }
+        }
+    }
+}
+
+impl PoolInner {
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn wait_while<F>(
+        &self,
+        timeout: Option<Duration>,
+        mut condition: F,
+    ) -> MutexGuard<'_, PoolState>
+    where
+        F: FnMut(&mut PoolState) -> bool,
+    {
+        let guard = self.lock();
+        match timeout {
+            Some(timeout) => {
+                let deadline = Instant::now() + timeout;
+                let mut guard = guard;
+                while condition(&mut guard) {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    guard = self
+                        .changed
+                        .wait_timeout(guard, deadline - now)
+                        .unwrap_or_else(|e| e.into_inner())
+                        .0;
+                }
+                guard
+            }
+            None => self
+                .changed
+                .wait_while(guard, condition)
+                .unwrap_or_else(|e| e.into_inner()),
+        }
+    }
+
+    /// 在并发数允许的范围内恢复挂起的任务、启动排队的任务，必要时抢占低优先级任务
+    fn schedule(self: &Arc<Self>, state: &mut PoolState) {
+        loop {
+            let head = state.queue.peek().map(|pending| pending.key);
+            if state.running < state.max_concurrency {
+                // 挂起的任务与排队的任务竞争空闲位置，优先级相同时先恢复挂起的任务
+                let suspended = state
+                    .suspended
+                    .iter()
+                    .filter_map(|task_id| Some((state.tasks.get(task_id)?.key, *task_id)))
+                    .max();
+                match (suspended, head) {
+                    (Some((key, task_id)), head) if head.is_none_or(|head| key.0 >= head.0) => {
+                        self.resume(state, task_id)
+                    }
+                    (_, Some(_)) => {
+                        if let Some(pending) = state.queue.pop() {
+                            self.start(state, pending);
+                        }
+                    }
+                    _ => break,
+                }
+            } else if state.preemption
+                && let Some(head) = head
+                && let Some(task_id) = Self::preemption_victim(state, head.0)
+            {
+                if !self.suspend(state, task_id) {
+                    self.retry_later(state);
+                    break;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// 优先级低于 `priority` 的运行中任务里，优先级最低、最晚提交的一个
+    fn preemption_victim(state: &PoolState, priority: i32) -> Option<u64> {
+        state
+            .tasks
+            .iter()
+            .filter(|(_, task)| task.status == TaskStatus::Running && task.key.0 < priority)
+            .min_by_key(|(_, task)| task.key)
+            .map(|(task_id, _)| *task_id)
+    }
+
+    fn start(self: &Arc<Self>, state: &mut PoolState, pending: Pending) {
+        let task_id = pending.executor.task_id();
+        state.running += 1;
+        if let Some(task) = state.tasks.get_mut(&task_id) {
+            task.status = TaskStatus::Running;
+        }
+        info!(
+            "启动任务: task_id: {task_id}, running: {}, queued: {}",
+            state.running,
+            state.queue.len()
+        );
+        let inner = self.clone();
+        self.handle.spawn(async move { inner.run(pending).await });
+    }
+
+    /// 挂起任务并让出位置，子进程尚未启动时返回 false
+    fn suspend(&self, state: &mut PoolState, task_id: u64) -> bool {
+        let Some(task) = state.tasks.get_mut(&task_id) else {
+            return false;
+        };
+        match task.process.suspend() {
+            Ok(true) => {}
+            Ok(false) => return false,
+            Err(e) => {
+                warn!("挂起任务失败: task_id: {task_id}, {e}");
+                return false;
+            }
+        }
+        task.status = TaskStatus::Suspended;
+        state.running -= 1;
+        state.suspended.push(task_id);
+        info!("任务被抢占: task_id: {task_id}");
+        true
+    }
+
+    /// 恢复挂起的任务；子进程已被取消时也按恢复处理，由任务结束时归还位置
+    fn resume(&self, state: &mut PoolState, task_id: u64) {
+        state.suspended.retain(|id| *id != task_id);
+        state.running += 1;
+        if let Some(task) = state.tasks.get_mut(&task_id) {
+            if let Err(e) = task.process.resume() {
+                warn!("恢复任务失败: task_id: {task_id}, {e}");
+            }
+            task.status = TaskStatus::Running;
+        }
+        info!("任务恢复运行: task_id: {task_id}");
+    }
+
+    fn retry_later(self: &Arc<Self>, state: &mut PoolState) {
+        if state.retry_pending {
+            return;
+        }
+        state.retry_pending = true;
+        let inner = self.clone();
+        self.handle.spawn(async move {
+            tokio::time::sleep(PREEMPT_RETRY_INTERVAL).await;
+            let mut state = inner.lock();
+            state.retry_pending = false;
+            inner.schedule(&mut state);
+        });
+    }
+
+    async fn run(self: Arc<Self>, pending: Pending) {
+        let Pending {
+            executor,
+            mut listener,
+            ..
+        } = pending;
+        let task_id = executor.task_id();
+        let result = executor.execute(&mut listener).await;
+        let status = TaskStatus::from_result(&result);
+        info!("任务结束: task_id: {task_id}, status: {status:?}");
+        listener.on_complete(status.error_code().unwrap_or_default());
+
+        let mut state = self.lock();
+        // 挂起中被取消的任务不占用位置
+        match state.suspended.iter().position(|id| *id == task_id) {
+            Some(index) => {
+                state.suspended.swap_remove(index);
+            }
+            None => state.running -= 1,
+        }
+        state.finish(task_id, status);
+        self.schedule(&mut state);
+        drop(state);
+        self.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::{ErrorMessage, ProgressMessage, ResultMessage};
+    use crate::retry::RetryPolicy;
+    use crate::test_util::NullListener;
+
+    /// 记录同时运行的任务数和任务启动、结束顺序
+    #[derive(Clone, Default)]
+    struct Activity {
+        active: Arc<Mutex<usize>>,
+        max_active: Arc<Mutex<usize>>,
+        started: Arc<Mutex<Vec<u64>>>,
+        finished: Arc<Mutex<Vec<u64>>>,
+    }
+
+    struct ActivityListener {
+        task_id: u64,
+        activity: Activity,
+    }
+
+    impl MessageListener for ActivityListener {
+        fn on_progress(&mut self, _progress: ProgressMessage) {
+            let mut active = self.activity.active.lock().unwrap();
+            *active += 1;
+            let mut max_active = self.activity.max_active.lock().unwrap();
+            *max_active = (*max_active).max(*active);
+            self.activity.started.lock().unwrap().push(self.task_id);
+        }
+        fn on_error(&mut self, _error: ErrorMessage) {}
+        fn on_result(&mut self, _result: ResultMessage) {}
+        fn on_complete(&mut self, error_code: i32) {
+            if error_code == 0 {
+                *self.activity.active.lock().unwrap() -= 1;
+            }
+            self.activity.finished.lock().unwrap().push(self.task_id);
+        }
+    }
+
+    fn sleeper(task_id: u64) -> TaskExecutor {
+        let script = "import json, time\n\
+                      print(json.dumps({'Progress': {'done': 0, 'size': 1}}), flush=True)\n\
+                      time.sleep(0.2)";
+        TaskExecutor::new("python".into(), vec!["-c".into(), script.into()]).with_task_id(task_id)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_bounded_parallelism() {
+        let pool = Arc::new(TaskPool::new(Handle::current()).with_max_concurrency(2));
+        let activity = Activity::default();
+        for task_id in 1..=5 {
+            let listener = ActivityListener {
+                task_id,
+                activity: activity.clone(),
+            };
+            pool.submit(sleeper(task_id), listener).unwrap();
+        }
+        assert_eq!(pool.running(), 2);
+        assert_eq!(pool.queued(), 3);
+        assert_eq!(pool.status(5), Some(TaskStatus::Queued));
+
+        let waiter = pool.clone();
+        let statuses = tokio::task::spawn_blocking(move || waiter.wait_all(None))
+            .await
+            .unwrap();
+        assert_eq!(statuses.len(), 5);
+        assert!(statuses.values().all(|s| *s == TaskStatus::Succeeded));
+        assert_eq!(*activity.max_active.lock().unwrap(), 2);
+        assert_eq!(pool.status(1), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_task_ids() {
+        let pool = TaskPool::new(Handle::current()).with_max_concurrency(1);
+        let executor = || TaskExecutor::new("python".into(), vec!["-c".into(), "pass".into()]);
+        let explicit = pool
+            .submit(executor().with_task_id(2), NullListener)
+            .unwrap();
+        let first = pool.submit(executor(), NullListener).unwrap();
+        let second = pool.submit(executor(), NullListener).unwrap();
+        assert_eq!((explicit, first, second), (2, 1, 3));
+        assert!(matches!(
+            pool.submit(executor().with_task_id(2), NullListener),
+            Err(PyRunnerError::InvalidParameter { .. })
+        ));
+
+        let statuses = tokio::task::spawn_blocking(move || pool.wait_all(None))
+            .await
+            .unwrap();
+        assert_eq!(statuses.len(), 3);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_finished_without_wait() {
+        let pool = TaskPool::new(Handle::current());
+        let executor =
+            TaskExecutor::new("python".into(), vec!["-c".into(), "pass".into()]).with_task_id(7);
+        pool.submit(executor, NullListener).unwrap();
+        while pool.running() > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // 无人等待的任务结束后也不再占用任务表
+        assert!(pool.inner.lock().tasks.is_empty());
+        assert_eq!(pool.status(7), Some(TaskStatus::Succeeded));
+        assert_eq!(pool.wait(7, None).unwrap(), TaskStatus::Succeeded);
+        assert_eq!(pool.status(7), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_priority_order() {
+        let pool = Arc::new(
+            TaskPool::new(Handle::current())
+                .with_max_concurrency(1)
+                .with_queue_order(QueueOrder::Priority),
+        );
+        let activity = Activity::default();
+        let listener = |task_id| ActivityListener {
+            task_id,
+            activity: activity.clone(),
+        };
+        pool.submit(sleeper(1), listener(1)).unwrap();
+        pool.submit_with_priority(sleeper(2), listener(2), 0)
+            .unwrap();
+        pool.submit_with_priority(sleeper(3), listener(3), 0)
+            .unwrap();
+        pool.submit_with_priority(sleeper(4), listener(4), 10)
+            .unwrap();
+        assert!(pool.cancel(3));
+        assert_eq!(pool.status(3), Some(TaskStatus::Cancelled));
+
+        let waiter = pool.clone();
+        let statuses = tokio::task::spawn_blocking(move || waiter.wait_all(None))
+            .await
+            .unwrap();
+        assert_eq!(statuses[&3], TaskStatus::Cancelled);
+        assert_eq!(*activity.started.lock().unwrap(), vec![1, 4, 2]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_preemption() {
+        let pool = Arc::new(
+            TaskPool::new(Handle::current())
+                .with_max_concurrency(1)
+                .with_queue_order(QueueOrder::Priority)
+                .with_preemption(true),
+        );
+        let activity = Activity::default();
+        let listener = |task_id| ActivityListener {
+            task_id,
+            activity: activity.clone(),
+        };
+
+        let script = "import json, time\n\
+                      print(json.dumps({'Progress': {'done': 0, 'size': 1}}), flush=True)\n\
+                      time.sleep(1)";
+        let background =
+            TaskExecutor::new("python".into(), vec!["-c".into(), script.into()]).with_task_id(1);
+        let process = background.process_handle();
+        pool.submit_with_priority(background, listener(1), PRIORITY_BACKGROUND)
+            .unwrap();
+        while activity.started.lock().unwrap().is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        pool.submit_with_priority(sleeper(2), listener(2), PRIORITY_INTERACTIVE)
+            .unwrap();
+        assert_eq!(pool.status(1), Some(TaskStatus::Suspended));
+        assert_eq!(pool.status(2), Some(TaskStatus::Running));
+        assert!(process.is_suspended());
+        // SIGSTOP 异步生效，稍等片刻再检查子进程状态
+        let stat = format!("/proc/{}/stat", process.pid().unwrap());
+        let mut stopped = false;
+        for _ in 0..50 {
+            stopped = std::fs::read_to_string(&stat).unwrap().contains(") T ");
+            if stopped {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(stopped);
+
+        let waiter = pool.clone();
+        let statuses = tokio::task::spawn_blocking(move || waiter.wait_all(None))
+            .await
+            .unwrap();
+        assert_eq!(statuses[&1], TaskStatus::Succeeded);
+        assert_eq!(statuses[&2], TaskStatus::Succeeded);
+        assert_eq!(*activity.finished.lock().unwrap(), vec![2, 1]);
+        assert_eq!(*activity.max_active.lock().unwrap(), 2);
+    }
+
+    /// 按顺序记录各任务的进度、重试和结束
+    struct EventLog {
+        task_id: u64,
+        events: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl MessageListener for EventLog {
+        fn on_progress(&mut self, _progress: ProgressMessage) {
+            let event = format!("progress {}", self.task_id);
+            self.events.lock().unwrap().push(event);
+        }
+        fn on_error(&mut self, _error: ErrorMessage) {}
+        fn on_result(&mut self, _result: ResultMessage) {}
+        fn on_retry(&mut self, _retry: crate::ipc::RetryMessage) {
+            let event = format!("retry {}", self.task_id);
+            self.events.lock().unwrap().push(event);
+        }
+        fn on_complete(&mut self, error_code: i32) {
+            let event = format!("complete {} {error_code}", self.task_id);
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_retry_while_suspended() {
+        let pool = Arc::new(
+            TaskPool::new(Handle::current())
+                .with_max_concurrency(1)
+                .with_queue_order(QueueOrder::Priority)
+                .with_preemption(true),
+        );
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let listener = |task_id| EventLog {
+            task_id,
+            events: events.clone(),
+        };
+
+        // 第一次执行一直睡眠，挂起期间超时后重试，第二次立即结束
+        let marker = std::env::temp_dir().join(format!("pyrunner-pool-{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+        let script = "import json, os, sys, time\n\
+                      print(json.dumps({'Progress': {'done': 0, 'size': 1}}), flush=True)\n\
+                      if not os.path.exists(sys.argv[1]):\n    \
+                          open(sys.argv[1], 'w').close()\n    \
+                          time.sleep(30)";
+        let background = TaskExecutor::new(
+            "python".into(),
+            vec![
+                "-c".into(),
+                script.into(),
+                marker.to_string_lossy().into_owned(),
+            ],
+        )
+        .with_task_id(1)
+        .with_timeout(Duration::from_secs(1))
+        .with_retry_policy(
+            RetryPolicy::new().with_backoff(Duration::from_millis(10), Duration::from_millis(10)),
+        );
+        pool.submit_with_priority(background, listener(1), PRIORITY_BACKGROUND)
+            .unwrap();
+        while events.lock().unwrap().is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let script = "import json, time\n\
+                      print(json.dumps({'Progress': {'done': 0, 'size': 1}}), flush=True)\n\
+                      time.sleep(1.5)";
+        let interactive =
+            TaskExecutor::new("python".into(), vec!["-c".into(), script.into()]).with_task_id(2);
+        pool.submit_with_priority(interactive, listener(2), PRIORITY_INTERACTIVE)
+            .unwrap();
+
+        let waiter = pool.clone();
+        let statuses = tokio::task::spawn_blocking(move || waiter.wait_all(None))
+            .await
+            .unwrap();
+        let _ = std::fs::remove_file(&marker);
+        assert_eq!(statuses[&1], TaskStatus::Succeeded);
+        assert_eq!(statuses[&2], TaskStatus::Succeeded);
+        // 重试的子进程在交互任务结束、挂起的任务恢复之后才启动
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "progress 1",
+                "progress 2",
+                "retry 1",
+                "complete 2 0",
+                "progress 1",
+                "complete 1 0"
+            ]
+        );
+        assert_eq!(pool.running(), 0);
+    }
+}
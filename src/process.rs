@@ -0,0 +1,232 @@
+use crate::error::Result;
+use nix::errno::Errno;
+use nix::sys::signal::{self, Signal};
+use nix::sys::wait::{Id, WaitPidFlag, WaitStatus, waitid};
+use nix::unistd::Pid;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, mpsc};
+use tokio::process::{Child, Command};
+use tokio::runtime::Handle;
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::{Notify, oneshot};
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// 子进程句柄，可在任意线程克隆并通过 SIGSTOP/SIGCONT 挂起、恢复正在运行的子进程
+///
+/// 子进程是独立进程组的首进程，信号发给整个进程组，脚本启动的孙进程一并挂起、恢复。
+/// 子进程启动前和回收后没有 pid，此时不能挂起；挂起期间子进程退出时挂起状态保留，
+/// 按重试策略重新执行的子进程等恢复后才启动
+#[derive(Clone, Default)]
+pub struct ProcessHandle {
+    inner: Arc<Mutex<ProcessState>>,
+    resumed: Arc<Notify>,
+}
+
+#[derive(Default)]
+struct ProcessState {
+    pid: Option<Pid>,
+    suspended: bool,
+    resumed_at: Option<Instant>,
+}
+
+impl ProcessHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pid(&self) -> Option<u32> {
+        self.lock().pid.map(|pid| pid.as_raw() as u32)
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.lock().suspended
+    }
+
+    /// 向子进程所在的进程组发送 SIGSTOP，子进程未运行或已挂起时返回 `Ok(false)`
+    pub fn suspend(&self) -> Result<bool> {
+        let mut state = self.lock();
+        let Some(pid) = state.pid else {
+            return Ok(false);
+        };
+        if state.suspended {
+            return Ok(false);
+        }
+        signal::killpg(pid, Signal::SIGSTOP)?;
+        state.suspended = true;
+        info!("子进程已挂起: pid: {pid}");
+        Ok(true)
+    }
+
+    /// 向子进程所在的进程组发送 SIGCONT，未挂起时返回 `Ok(false)`
+    pub fn resume(&self) -> Result<bool> {
+        let mut state = self.lock();
+        if !state.suspended {
+            return Ok(false);
+        }
+        match state.pid {
+            Some(pid) => {
+                signal::killpg(pid, Signal::SIGCONT)?;
+                info!("子进程已恢复: pid: {pid}");
+            }
+            None => info!("任务已恢复，可以重新执行子进程"),
+        }
+        state.suspended = false;
+        state.resumed_at = Some(Instant::now());
+        self.resumed.notify_waiters();
+        Ok(true)
+    }
+
+    /// 让挂起的子进程继续运行以便处理终止信号，挂起状态保持不变
+    pub(crate) fn continue_stopped(&self) -> Result<()> {
+        let state = self.lock();
+        if let Some(pid) = state.pid
+            && state.suspended
+        {
+            signal::killpg(pid, Signal::SIGCONT)?;
+        }
+        Ok(())
+    }
+
+    /// 等待挂起的任务被恢复，未挂起时立即返回
+    pub(crate) async fn wait_resumed(&self) {
+        loop {
+            let resumed = self.resumed.notified();
+            if !self.is_suspended() {
+                return;
+            }
+            resumed.await;
+        }
+    }
+
+    /// 最近一次恢复的时间，执行器据此顺延空闲超时
+    pub(crate) fn resumed_at(&self) -> Option<Instant> {
+        self.lock().resumed_at
+    }
+
+    /// 记录子进程的 pid，返回的守卫释放时清除，并结束进程组中残留的进程；挂起状态不随之清除
+    ///
+    /// 子进程正常回收时应在回收之前调用 `kill_group`，守卫释放时只处理任务被中途丢弃的情况
+    pub(crate) fn attach(&self, pid: u32) -> AttachGuard<'_> {
+        let mut state = self.lock();
+        state.pid = Some(Pid::from_raw(pid as i32));
+        state.suspended = false;
+        state.resumed_at = None;
+        AttachGuard { handle: self }
+    }
+
+    /// 结束进程组中残留的进程并清除 pid，守卫释放时不再重复发送
+    ///
+    /// 必须在子进程退出之后、回收之前调用：回收之后 pid 可能已被其他进程复用
+    pub(crate) fn kill_group(&self) {
+        if let Some(pid) = self.lock().pid.take() {
+            kill_group(pid);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ProcessState> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl std::fmt::Debug for ProcessHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.lock();
+        f.debug_struct("ProcessHandle")
+            .field("pid", &state.pid)
+            .field("suspended", &state.suspended)
+            .finish()
+    }
+}
+
+struct SpawnRequest {
+    command: Command,
+    handle: Handle,
+    reply: oneshot::Sender<std::io::Result<Child>>,
+}
+
+/// 在专用线程上创建子进程
+///
+/// `PR_SET_PDEATHSIG` 跟随的是调用 fork 的线程：工作线程经 `block_in_place` 变成阻塞线程后
+/// 可能随时退出，子进程会被误杀。专用线程与宿主进程同生命周期，只有宿主退出时子进程才会收到信号
+pub(crate) async fn spawn_child(command: Command) -> std::io::Result<Child> {
+    static SPAWNER: OnceLock<Option<mpsc::Sender<SpawnRequest>>> = OnceLock::new();
+    let spawner = SPAWNER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<SpawnRequest>();
+        let spawned = std::thread::Builder::new()
+            .name("pyrunner-spawner".into())
+            .spawn(move || {
+                for SpawnRequest {
+                    mut command,
+                    handle,
+                    reply,
+                } in receiver
+                {
+                    // 子进程的管道和回收注册到调用方所在的运行时
+                    let _runtime = handle.enter();
+                    // 调用方已放弃等待时，子进程随 `kill_on_drop` 一起结束
+                    let _ = reply.send(command.spawn());
+                }
+            });
+        match spawned {
+            Ok(_) => Some(sender),
+            Err(e) => {
+                warn!("创建子进程的线程启动失败: {e}");
+                None
+            }
+        }
+    });
+
+    let (reply, child) = oneshot::channel();
+    let request = SpawnRequest {
+        command,
+        handle: Handle::current(),
+        reply,
+    };
+    spawner
+        .as_ref()
+        .and_then(|spawner| spawner.send(request).ok())
+        .ok_or_else(|| std::io::Error::other("创建子进程的线程不可用"))?;
+    child
+        .await
+        .map_err(|_| std::io::Error::other("创建子进程的线程已退出"))?
+}
+
+/// 等待子进程退出但不回收
+///
+/// 退出后尚未回收的子进程是僵尸进程，仍占用 pid 和进程组 id，此时向进程组发送信号不会误伤其他进程
+pub(crate) async fn wait_exited(pid: u32) -> std::io::Result<()> {
+    // 先注册 SIGCHLD 再检查，检查之后才到达的信号不会丢失
+    let mut sigchld = signal(SignalKind::child())?;
+    let pid = Pid::from_raw(pid as i32);
+    let flags = WaitPidFlag::WEXITED | WaitPidFlag::WNOHANG | WaitPidFlag::WNOWAIT;
+    loop {
+        match waitid(Id::Pid(pid), flags) {
+            Ok(WaitStatus::StillAlive) => {}
+            Ok(_) => return Ok(()),
+            Err(Errno::EINTR) => continue,
+            Err(e) => return Err(e.into()),
+        }
+        if sigchld.recv().await.is_none() {
+            return Err(std::io::Error::other("SIGCHLD信号已不可用"));
+        }
+    }
+}
+
+fn kill_group(pid: Pid) {
+    match signal::killpg(pid, Signal::SIGKILL) {
+        Ok(()) => info!("已结束进程组: pgid: {pid}"),
+        Err(Errno::ESRCH) => {}
+        Err(e) => warn!("结束进程组失败: pgid: {pid}, {e}"),
+    }
+}
+
+pub(crate) struct AttachGuard<'a> {
+    handle: &'a ProcessHandle,
+}
+
+impl Drop for AttachGuard<'_> {
+    /// 任务被丢弃时守卫先于 `Child` 释放，子进程此时还未回收，脚本启动的孙进程随进程组一起结束
+    fn drop(&mut self) {
+        self.handle.kill_group();
+    }
+}
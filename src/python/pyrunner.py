@@ -0,0 +1,361 @@
+"""
+pyrunner 消息协议的 Python 客户端
+
+由 Rust 侧的 TaskExecutor 释放到子进程的 PYTHONPATH 中，脚本只需:
+
+    import pyrunner
+
+    with pyrunner.task():
+        for i in range(n):
+            ...
+            pyrunner.report_progress(i + 1, n)
+        pyrunner.report_result(pages, words)
+
+不是文档转换的脚本可以用 report_output 返回任意结果:
+
+    pyrunner.report_output({"text": text}, artifacts=["/path/to/out.txt"])
+
+第一条消息发送前会自动发送 Hello 握手消息，也可以调用 hello() 主动声明脚本名称和能力。
+
+runner 开启控制通道时，脚本可以在循环中调用 checkpoint() 响应暂停和协作式取消，
+并用 on_request() 注册处理 runner 请求的函数:
+
+    pyrunner.on_request(lambda payload: {"password": ask_password()})
+    for page in pages:
+        pyrunner.checkpoint()  # 暂停时阻塞，取消时抛出 pyrunner.Cancelled
+        ...
+
+需要用户输入（如加密 PDF 的密码）时，request_password() 会向 runner 请求密码，
+直到 check 通过或超过尝试次数（抛出 pyrunner.WrongPassword）:
+
+    password = pyrunner.request_password("请输入密码", lambda p: doc.authenticate(p))
+
+消息的写入位置和协议版本由环境变量决定:
+- PYRUNNER_MESSAGE_FD: 写入专用的消息管道，否则写入 stdout
+- PYRUNNER_MESSAGE_PREFIX: 每条消息前附加的分帧前缀
+- PYRUNNER_PROTOCOL_VERSION: runner 支持的协议版本，低于本模块版本时降级
+- PYRUNNER_CONTROL: 为 "stdin" 时从 stdin 读取 runner 的控制命令
+- PYRUNNER_INPUT_ATTEMPTS: 同类输入最多请求的次数
+"""
+
+import json
+import os
+import queue
+import sys
+import threading
+
+__all__ = [
+    "Cancelled",
+    "PROTOCOL_VERSION",
+    "PYTHON_ERROR_CODE",
+    "TASK_EXECUTION_FAILED_CODE",
+    "WRONG_PASSWORD_CODE",
+    "WrongPassword",
+    "checkpoint",
+    "hello",
+    "is_cancelled",
+    "on_request",
+    "request_input",
+    "request_password",
+    "report_progress",
+    "report_error",
+    "report_result",
+    "report_output",
+    "task",
+]
+
+# 与 Rust 侧 PyRunnerError::error_code() 保持一致
+TASK_EXECUTION_FAILED_CODE = 1001
+PYTHON_ERROR_CODE = 2001
+WRONG_PASSWORD_CODE = 4004
+
+# 与 Rust 侧 ipc::PROTOCOL_VERSION 保持一致
+PROTOCOL_VERSION = 1
+
+_lock = threading.Lock()
+_stream = None
+_hello_sent = False
+
+
+class Cancelled(Exception):
+    """runner 发送了协作式取消命令"""
+
+
+class WrongPassword(Exception):
+    """密码超过尝试次数仍然错误"""
+
+    error_code = WRONG_PASSWORD_CODE
+
+
+class _Control:
+    """在后台线程中读取 stdin 上的控制命令，runner 的请求交给另一个线程处理"""
+
+    def __init__(self):
+        self.resumed = threading.Event()
+        self.resumed.set()
+        self.cancelled = threading.Event()
+        self.handler = None
+        self.requests = queue.Queue()
+        self.inputs = {}
+        self.next_input_id = 0
+
+    def start(self):
+        threading.Thread(target=self._run, name="pyrunner-control", daemon=True).start()
+        threading.Thread(
+            target=self._serve_requests, name="pyrunner-requests", daemon=True
+        ).start()
+
+    def _run(self):
+        # 直接读取 fd，避免守护线程在解释器退出时持有 sys.stdin 的锁
+        buffer = b""
+        while True:
+            chunk = os.read(0, 4096)
+            if not chunk:
+                return
+            buffer += chunk
+            while b"\n" in buffer:
+                line, buffer = buffer.split(b"\n", 1)
+                if line.strip():
+                    self._dispatch(line)
+
+    def _dispatch(self, line):
+        # 一行命令有误只丢弃这一行，读取线程退出后取消和输入都将无法送达
+        try:
+            command = json.loads(line)
+        except ValueError as e:
+            _warn("忽略无法解析的控制命令: %s: %r" % (e, line))
+            return
+        try:
+            self._handle(command)
+        except Exception as e:
+            _warn("处理控制命令失败: %s: %s: %r" % (type(e).__name__, e, line))
+
+    def _handle(self, command):
+        if command == "Pause":
+            self.resumed.clear()
+        elif command == "Resume":
+            self.resumed.set()
+        elif command == "Cancel":
+            self.cancelled.set()
+            self.resumed.set()
+        elif isinstance(command, dict) and "Request" in command:
+            # 处理函数可能很慢，不能阻塞读取线程
+            self.requests.put(command["Request"])
+        elif isinstance(command, dict) and "Input" in command:
+            reply = command["Input"]
+            pending = self.inputs.get(reply["id"])
+            if pending is not None:
+                pending["value"] = reply.get("value")
+                pending["event"].set()
+
+    def request_input(self, kind, prompt, attempt):
+        with _lock:
+            self.next_input_id += 1
+            input_id = self.next_input_id
+        pending = {"event": threading.Event(), "value": None}
+        self.inputs[input_id] = pending
+        try:
+            _send(
+                "NeedsInput",
+                {"id": input_id, "kind": kind, "prompt": str(prompt), "attempt": attempt},
+            )
+            pending["event"].wait()
+        finally:
+            del self.inputs[input_id]
+        if pending["value"] is None:
+            # 用户放弃输入，runner 按取消处理
+            self.cancelled.set()
+            raise Cancelled()
+        return pending["value"]
+
+    def _serve_requests(self):
+        while True:
+            self._respond(self.requests.get())
+
+    def _respond(self, request):
+        response = {"id": request["id"], "payload": None, "error": None}
+        if self.handler is None:
+            response["error"] = "no request handler"
+        else:
+            try:
+                response["payload"] = self.handler(request.get("payload"))
+            except Exception as e:
+                response["error"] = "%s: %s" % (type(e).__name__, e)
+        _send("Response", response)
+
+
+def _warn(message):
+    sys.stderr.write("pyrunner: %s\n" % message)
+    sys.stderr.flush()
+
+
+_control = None
+if os.environ.get("PYRUNNER_CONTROL") == "stdin":
+    _control = _Control()
+    _control.start()
+
+
+def _message_stream():
+    global _stream
+    if _stream is None:
+        fd = os.environ.get("PYRUNNER_MESSAGE_FD")
+        if fd:
+            _stream = os.fdopen(int(fd), "w", encoding="utf-8", buffering=1)
+        else:
+            _stream = sys.stdout
+    return _stream
+
+
+def _protocol_version():
+    runner_version = os.environ.get("PYRUNNER_PROTOCOL_VERSION")
+    if runner_version:
+        return min(PROTOCOL_VERSION, int(runner_version))
+    return PROTOCOL_VERSION
+
+
+def _write(kind, payload):
+    line = os.environ.get("PYRUNNER_MESSAGE_PREFIX", "") + json.dumps(
+        {kind: payload}, ensure_ascii=False
+    )
+    stream = _message_stream()
+    stream.write(line + "\n")
+    stream.flush()
+
+
+def _send(kind, payload):
+    global _hello_sent
+    with _lock:
+        if not _hello_sent:
+            _hello_sent = True
+            if kind != "Hello":
+                _write("Hello", _hello_payload(None, ()))
+        _write(kind, payload)
+
+
+def _hello_payload(script, capabilities):
+    return {
+        "protocol_version": _protocol_version(),
+        "script": script or os.path.basename(sys.argv[0]) or None,
+        "capabilities": [str(capability) for capability in capabilities]
+        + (["control"] if _control is not None else []),
+    }
+
+
+def hello(script=None, capabilities=()):
+    """声明协议版本、脚本名称和能力，必须在其他消息之前调用，否则忽略"""
+    if _hello_sent:
+        return
+    _send("Hello", _hello_payload(script, capabilities))
+
+
+def report_progress(
+    done,
+    size=0,
+    stage=None,
+    stage_index=None,
+    stage_count=None,
+    message=None,
+    unit=None,
+    indeterminate=False,
+):
+    """
+    报告进度，除 done/size 外的参数都是可选的:
+    - stage/stage_index/stage_count: 当前阶段名称及序号（从 1 开始）
+    - message: 面向用户的进度描述
+    - unit: "bytes"、"pages" 或 "items"
+    - indeterminate: 无法给出总量时为 True
+    """
+    payload = {"done": int(done), "size": int(size)}
+    optional = {
+        "stage": stage,
+        "stage_index": stage_index,
+        "stage_count": stage_count,
+        "message": message,
+        "unit": unit,
+    }
+    payload.update((key, value) for key, value in optional.items() if value is not None)
+    if indeterminate:
+        payload["indeterminate"] = True
+    _send("Progress", payload)
+
+
+def report_error(error_code, error_message):
+    """报告错误，不会终止脚本"""
+    _send("Error", {"error_code": int(error_code), "error_message": str(error_message)})
+
+
+def report_result(pages, words):
+    """报告文档转换的结果（页数、字数）"""
+    _send("Result", {"pages": int(pages), "words": int(words)})
+
+
+def report_output(value=None, artifacts=()):
+    """报告通用结果：value 为任意可 JSON 序列化的值，artifacts 为输出文件路径"""
+    _send("Output", {"value": value, "artifacts": [str(path) for path in artifacts]})
+
+
+def on_request(handler):
+    """
+    注册处理 runner 请求的函数：handler(payload) 的返回值作为应答，抛出异常则应答错误
+
+    handler 在专门的线程中按请求顺序执行，执行期间仍能收到暂停、取消和输入
+    """
+    if _control is not None:
+        _control.handler = handler
+    return handler
+
+
+def request_input(kind, prompt, attempt=1):
+    """
+    向 runner 请求用户输入并阻塞等待，kind 为 "password" 或 "text"
+
+    用户放弃输入时抛出 Cancelled；未开启控制通道时抛出 RuntimeError
+    """
+    if _control is None:
+        raise RuntimeError("runner未开启控制通道，无法请求输入")
+    return _control.request_input(kind, prompt, attempt)
+
+
+def request_password(prompt, check, max_attempts=None):
+    """反复请求密码直到 check(password) 为真，超过尝试次数抛出 WrongPassword"""
+    if max_attempts is None:
+        max_attempts = int(os.environ.get("PYRUNNER_INPUT_ATTEMPTS", "3"))
+    for attempt in range(1, max_attempts + 1):
+        password = request_input("password", prompt, attempt)
+        if check(password):
+            return password
+    raise WrongPassword("已尝试%d次" % max_attempts)
+
+
+def is_cancelled():
+    return _control is not None and _control.cancelled.is_set()
+
+
+def checkpoint():
+    """暂停时阻塞直到恢复，收到取消命令时抛出 Cancelled；未开启控制通道时立即返回"""
+    if _control is None:
+        return
+    _control.resumed.wait()
+    if _control.cancelled.is_set():
+        raise Cancelled()
+
+
+class task:
+    """
+    上下文管理器：将未捕获的异常以 Error 消息报告后继续抛出，
+    子进程仍以非零状态退出，Rust 侧可以同时拿到 Error 消息和 Python 调用栈
+    """
+
+    def __init__(self, error_code=PYTHON_ERROR_CODE):
+        self.error_code = error_code
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, exc_type, exc_value, traceback):
+        if exc_type is not None and not issubclass(
+            exc_type, (Cancelled, KeyboardInterrupt, SystemExit)
+        ):
+            error_code = getattr(exc_value, "error_code", self.error_code)
+            report_error(error_code, "%s: %s" % (exc_type.__name__, exc_value))
+        return False
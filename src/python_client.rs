@@ -0,0 +1,30 @@
+use crate::error::Result;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Python 客户端模块名，脚本中通过 `import pyrunner` 使用
+pub const PYTHON_CLIENT_MODULE: &str = "pyrunner";
+
+/// 内嵌的 Python 客户端源码
+pub const PYTHON_CLIENT_SOURCE: &str = include_str!("python/pyrunner.py");
+
+/// 将 Python 客户端写入 `dir`，返回模块文件路径；内容未变化时不会重复写入
+pub fn install_python_client(dir: &Path) -> Result<PathBuf> {
+    static NEXT_TMP_ID: AtomicU64 = AtomicU64::new(0);
+
+    let path = dir.join(format!("{PYTHON_CLIENT_MODULE}.py"));
+    if std::fs::read_to_string(&path).is_ok_and(|source| source == PYTHON_CLIENT_SOURCE) {
+        return Ok(path);
+    }
+
+    std::fs::create_dir_all(dir)?;
+    // 先写临时文件再重命名，避免并发启动的子进程导入写了一半的模块
+    let tmp = dir.join(format!(
+        ".{PYTHON_CLIENT_MODULE}.py.{}.{}",
+        std::process::id(),
+        NEXT_TMP_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&tmp, PYTHON_CLIENT_SOURCE)?;
+    std::fs::rename(&tmp, &path)?;
+    Ok(path)
+}
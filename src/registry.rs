@@ -0,0 +1,337 @@
+use crate::cancel::CancelHandle;
+use crate::control::ControlHandle;
+use crate::error::{PyRunnerError, Result};
+use crate::executor::TaskExecutor;
+use crate::listener::MessageListener;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskStatus {
+    /// 在 `TaskPool` 中排队，尚未启动
+    Queued,
+    Running,
+    /// 被 `TaskPool` 抢占而挂起
+    Suspended,
+    Succeeded,
+    Failed(i32),
+    Cancelled,
+}
+
+impl TaskStatus {
+    pub(crate) fn from_result(result: &Result<()>) -> Self {
+        match result {
+            Ok(()) => Self::Succeeded,
+            Err(PyRunnerError::TaskCancelled { .. }) => Self::Cancelled,
+            Err(e) => Self::Failed(e.error_code()),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, Self::Queued | Self::Running | Self::Suspended)
+    }
+
+    /// 任务结束时的错误码，0 表示成功
+    pub fn error_code(&self) -> Option<i32> {
+        match self {
+            Self::Queued | Self::Running | Self::Suspended => None,
+            Self::Succeeded => Some(0),
+            Self::Failed(code) => Some(*code),
+            Self::Cancelled => Some(PyRunnerError::TaskCancelled { task_id: 0 }.error_code()),
+        }
+    }
+}
+
+/// 结束后无人等待的任务最多保留多少个状态，供之后的 `status`/`wait` 查询
+const RECENT_FINISHED: usize = 256;
+
+/// 最近结束的任务状态，超过 `RECENT_FINISHED` 个时丢弃最早结束的
+#[derive(Default)]
+pub(crate) struct RecentFinished(VecDeque<(u64, TaskStatus)>);
+
+impl RecentFinished {
+    pub(crate) fn push(&mut self, task_id: u64, status: TaskStatus) {
+        if self.0.len() >= RECENT_FINISHED {
+            self.0.pop_front();
+        }
+        self.0.push_back((task_id, status));
+    }
+
+    pub(crate) fn get(&self, task_id: u64) -> Option<TaskStatus> {
+        self.0
+            .iter()
+            .rev()
+            .find(|(id, _)| *id == task_id)
+            .map(|(_, status)| *status)
+    }
+
+    /// 移除任务的状态，返回最近一次结束时的状态
+    pub(crate) fn remove(&mut self, task_id: u64) -> Option<TaskStatus> {
+        let status = self.get(task_id);
+        self.0.retain(|(id, _)| *id != task_id);
+        status
+    }
+
+    /// 取出全部状态，同一个 task_id 只保留最近一次
+    pub(crate) fn take_all(&mut self) -> HashMap<u64, TaskStatus> {
+        self.0.drain(..).collect()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+
+struct TaskEntry {
+    cancel: CancelHandle,
+    control: Option<ControlHandle>,
+    state: Arc<(Mutex<TaskStatus>, Condvar)>,
+}
+
+#[derive(Default)]
+struct Tasks {
+    running: HashMap<u64, TaskEntry>,
+    finished: RecentFinished,
+}
+
+impl Tasks {
+    fn finish(&mut self, task_id: u64, status: TaskStatus) {
+        self.running.remove(&task_id);
+        self.finished.push(task_id, status);
+    }
+
+    fn finished(&self, task_id: u64) -> Option<TaskStatus> {
+        self.finished.get(task_id)
+    }
+
+    fn forget(&mut self, task_id: u64) {
+        self.finished.remove(task_id);
+    }
+}
+
+/// 在给定的 Tokio 运行时上异步执行任务，并按 task_id 提供取消、查询和等待
+///
+/// 任务结束后即从注册表中移除，只保留最近结束的若干个任务的状态
+pub struct TaskRegistry {
+    handle: Handle,
+    tasks: Arc<Mutex<Tasks>>,
+}
+
+impl TaskRegistry {
+    pub fn new(handle: Handle) -> Self {
+        Self {
+            handle,
+            tasks: Arc::default(),
+        }
+    }
+
+    /// 提交任务后立即返回，任务结束时通过 `listener.on_complete` 通知
+    pub fn submit<L>(&self, executor: TaskExecutor, mut listener: L) -> u64
+    where
+        L: MessageListener + Send + 'static,
+    {
+        let task_id = executor.task_id();
+        let state = Arc::new((Mutex::new(TaskStatus::Running), Condvar::new()));
+        let entry = TaskEntry {
+            cancel: executor.cancel_handle(),
+            control: executor.control_handle(),
+            state: state.clone(),
+        };
+        {
+            let mut tasks = self.lock_tasks();
+            tasks.forget(task_id);
+            if tasks.running.insert(task_id, entry).is_some() {
+                warn!("task_id重复，旧任务将无法再被查询: {task_id}");
+            }
+        }
+
+        let tasks = self.tasks.clone();
+        self.handle.spawn(async move {
+            let result = executor.execute(&mut listener).await;
+            let status = TaskStatus::from_result(&result);
+            info!("任务结束: task_id: {task_id}, status: {status:?}");
+            listener.on_complete(status.error_code().unwrap_or_default());
+
+            // 先更新注册表再唤醒等待者，等待者醒来后总能在已结束列表中找到任务
+            let mut tasks = tasks.lock().unwrap_or_else(|e| e.into_inner());
+            let current = tasks
+                .running
+                .get(&task_id)
+                .is_some_and(|entry| Arc::ptr_eq(&entry.state, &state));
+            if current {
+                tasks.finish(task_id, status);
+            }
+            drop(tasks);
+
+            let (lock, cvar) = &*state;
+            *lock.lock().unwrap_or_else(|e| e.into_inner()) = status;
+            cvar.notify_all();
+        });
+
+        task_id
+    }
+
+    /// 请求取消任务，任务不存在时返回 false
+    pub fn cancel(&self, task_id: u64) -> bool {
+        match self.lock_tasks().running.get(&task_id) {
+            Some(entry) => {
+                entry.cancel.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 任务的控制句柄，任务不存在或未开启控制通道时返回 `None`
+    pub fn control(&self, task_id: u64) -> Option<ControlHandle> {
+        self.lock_tasks().running.get(&task_id)?.control.clone()
+    }
+
+    pub fn status(&self, task_id: u64) -> Option<TaskStatus> {
+        let tasks = self.lock_tasks();
+        match tasks.running.get(&task_id) {
+            Some(entry) => Some(*entry.state.0.lock().unwrap_or_else(|e| e.into_inner())),
+            None => tasks.finished(task_id),
+        }
+    }
+
+    /// 阻塞等待任务结束，`timeout` 为 `None` 时无限等待
+    ///
+    /// 超时返回 `TaskStatus::Running`；返回结束状态后不能再查询该任务
+    pub fn wait(&self, task_id: u64, timeout: Option<Duration>) -> Result<TaskStatus> {
+        let state = {
+            let mut tasks = self.lock_tasks();
+            match tasks.running.get(&task_id) {
+                Some(entry) => entry.state.clone(),
+                None => match tasks.finished(task_id) {
+                    Some(status) => {
+                        tasks.forget(task_id);
+                        return Ok(status);
+                    }
+                    None => {
+                        return Err(PyRunnerError::InvalidParameter {
+                            parameter: "task_id".into(),
+                            value: task_id.to_string(),
+                        });
+                    }
+                },
+            }
+        };
+
+        let (lock, cvar) = &*state;
+        let guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        let status = match timeout {
+            Some(timeout) => {
+                *cvar
+                    .wait_timeout_while(guard, timeout, |status| !status.is_finished())
+                    .unwrap_or_else(|e| e.into_inner())
+                    .0
+            }
+            None => *cvar
+                .wait_while(guard, |status| !status.is_finished())
+                .unwrap_or_else(|e| e.into_inner()),
+        };
+
+        if status.is_finished() {
+            self.lock_tasks().forget(task_id);
+        }
+        Ok(status)
+    }
+
+    fn lock_tasks(&self) -> std::sync::MutexGuard<'_, Tasks> {
+        self.tasks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::{ErrorMessage, ProgressMessage, ResultMessage};
+
+    #[derive(Default)]
+    struct CompleteListener {
+        complete: Arc<Mutex<Option<i32>>>,
+    }
+    impl MessageListener for CompleteListener {
+        fn on_progress(&mut self, _progress: ProgressMessage) {}
+        fn on_error(&mut self, _error: ErrorMessage) {}
+        fn on_result(&mut self, _result: ResultMessage) {}
+        fn on_complete(&mut self, error_code: i32) {
+            *self.complete.lock().unwrap() = Some(error_code);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_submit_and_wait() {
+        let registry = Arc::new(TaskRegistry::new(Handle::current()));
+        let listener = CompleteListener::default();
+        let complete = listener.complete.clone();
+        let executor = TaskExecutor::new("python".into(), vec!["src/demo_progress.py".into()])
+            .with_task_id(11);
+
+        let task_id = registry.submit(executor, listener);
+        assert_eq!(task_id, 11);
+        assert_eq!(registry.status(task_id), Some(TaskStatus::Running));
+
+        let waiter = registry.clone();
+        let status = tokio::task::spawn_blocking(move || waiter.wait(task_id, None))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(status, TaskStatus::Succeeded);
+        assert_eq!(*complete.lock().unwrap(), Some(0));
+        assert_eq!(registry.status(task_id), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_cancel() {
+        let registry = Arc::new(TaskRegistry::new(Handle::current()));
+        let executor = TaskExecutor::new(
+            "python".into(),
+            vec!["-c".into(), "import time; time.sleep(30)".into()],
+        )
+        .with_task_id(12);
+        let task_id = registry.submit(executor, CompleteListener::default());
+
+        let waiter = registry.clone();
+        let status = tokio::task::spawn_blocking(move || {
+            let status = waiter.wait(task_id, Some(Duration::from_millis(100)))?;
+            assert_eq!(status, TaskStatus::Running);
+            assert!(waiter.cancel(task_id));
+            waiter.wait(task_id, Some(Duration::from_secs(10)))
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(status, TaskStatus::Cancelled);
+        assert!(!registry.cancel(task_id));
+        assert!(registry.wait(task_id, None).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_finished_without_wait() {
+        let registry = TaskRegistry::new(Handle::current());
+        let listener = CompleteListener::default();
+        let complete = listener.complete.clone();
+        let executor =
+            TaskExecutor::new("python".into(), vec!["-c".into(), "pass".into()]).with_task_id(13);
+        let task_id = registry.submit(executor, listener);
+        while complete.lock().unwrap().is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        while registry.lock_tasks().running.contains_key(&task_id) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(registry.status(task_id), Some(TaskStatus::Succeeded));
+        for task_id in 100..100 + RECENT_FINISHED as u64 {
+            registry.lock_tasks().finish(task_id, TaskStatus::Succeeded);
+        }
+        assert_eq!(registry.status(task_id), None);
+        assert_eq!(registry.lock_tasks().finished.len(), RECENT_FINISHED);
+    }
+}
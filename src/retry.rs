@@ -0,0 +1,163 @@
+use crate::error::PyRunnerError;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::time::Duration;
+
+/// 默认最多执行的次数，包含第一次
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+/// 第一次重试前的默认等待时长
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// 重试等待时长的默认上限
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// 任务失败后的重试策略：指数退避加随机抖动
+///
+/// 是否重试依次判断：次数已用完、`is_fatal` 的错误不重试；按错误码设置过的以设置为准；
+/// 其余由 `is_retryable` 决定
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: f64,
+    overrides: HashMap<i32, bool>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            multiplier: 2.0,
+            jitter: 0.5,
+            overrides: HashMap::new(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 最多执行的次数，包含第一次，小于 1 时按 1 处理
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// 第一次重试前等待 `initial`，之后每次乘以倍数，但不超过 `max`
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    /// 每次重试等待时长的增长倍数，默认 2
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier.max(1.0);
+        self
+    }
+
+    /// 随机缩短等待时长的最大比例，取值 0 到 1，默认 0.5；多个任务同时失败时避免一起重试
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    /// 指定错误码的错误总是重试（`is_fatal` 的错误除外）
+    pub fn with_retry_code(mut self, error_code: i32) -> Self {
+        self.overrides.insert(error_code, true);
+        self
+    }
+
+    /// 指定错误码的错误从不重试
+    pub fn with_no_retry_code(mut self, error_code: i32) -> Self {
+        self.overrides.insert(error_code, false);
+        self
+    }
+
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// 第 `attempt` 次执行（从 1 开始）以 `error` 失败后是否应再执行一次
+    pub fn should_retry(&self, error: &PyRunnerError, attempt: u32) -> bool {
+        if attempt >= self.max_attempts || error.is_fatal() {
+            return false;
+        }
+        match self.overrides.get(&error.error_code()) {
+            Some(&retry) => retry,
+            None => error.is_retryable(),
+        }
+    }
+
+    /// 第 `attempt` 次执行（从 1 开始）失败后，重新执行前等待的时长
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let base = self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent);
+        let base = base.min(self.max_backoff.as_secs_f64());
+        let factor = 1.0 - self.jitter * random_fraction();
+        Duration::from_secs_f64(base * factor)
+    }
+}
+
+/// `[0, 1)` 内的随机数，借用标准库哈希的随机种子，不引入额外依赖
+fn random_fraction() -> f64 {
+    let bits = RandomState::new().hash_one(std::time::Instant::now());
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_should_retry() {
+        let policy = RetryPolicy::new()
+            .with_max_attempts(3)
+            .with_retry_code(2001)
+            .with_no_retry_code(9004);
+        let timeout = PyRunnerError::task_timeout(1);
+        assert!(policy.should_retry(&timeout, 1));
+        assert!(policy.should_retry(&timeout, 2));
+        assert!(!policy.should_retry(&timeout, 3));
+
+        let cancelled = PyRunnerError::TaskCancelled { task_id: 1 };
+        assert!(!policy.should_retry(&cancelled, 1));
+
+        let python = PyRunnerError::python_error("RuntimeError", "ocr failed");
+        assert!(!RetryPolicy::new().should_retry(&python, 1));
+        assert!(policy.should_retry(&python, 1));
+
+        let exhausted = PyRunnerError::ResourceExhausted {
+            resource: "memory".into(),
+        };
+        assert!(RetryPolicy::new().should_retry(&exhausted, 1));
+        assert!(!policy.should_retry(&exhausted, 1));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy::new()
+            .with_backoff(Duration::from_millis(100), Duration::from_millis(500))
+            .with_jitter(0.0);
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(4), Duration::from_millis(500));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_millis(500));
+
+        let policy = policy.with_jitter(0.5);
+        for attempt in 1..10 {
+            let delay = policy.backoff(attempt);
+            let base =
+                Duration::from_millis(100 * (1 << (attempt - 1))).min(Duration::from_millis(500));
+            assert!(delay <= base && delay >= base / 2, "{attempt}: {delay:?}");
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+use std::future::{Future, poll_fn};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+
+use crate::control::InputResponder;
+use crate::error::Result;
+use crate::ipc::{
+    ErrorMessage, HelloMessage, Message, NeedsInputMessage, OutputMessage, ProgressMessage,
+    ResponseMessage, ResultMessage, RetryMessage,
+};
+use crate::listener::{MessageListener, OutputStream};
+
+/// 任务执行过程中产生的事件，`Exit` 总是最后一个
+#[derive(Debug)]
+pub enum TaskEvent {
+    /// 协议消息：进度、错误、结果等
+    Message(Message),
+    /// 输入请求，通过 `responder` 应答
+    NeedsInput(NeedsInputMessage, InputResponder),
+    /// 子进程输出的普通文本行
+    Text(String, OutputStream),
+    /// 形似协议消息但无法解析的行
+    ParseError(String, serde_json::Error),
+    /// 任务结束，与 `execute` 的返回值相同
+    Exit(Result<()>),
+}
+
+impl TaskEvent {
+    /// 交给回调风格的监听器处理，`Exit` 不会产生回调
+    pub fn dispatch<L: MessageListener + ?Sized>(self, listener: &mut L) {
+        match self {
+            Self::Message(message) => listener.dispatch(message),
+            Self::NeedsInput(request, responder) => listener.on_needs_input(request, responder),
+            Self::Text(line, OutputStream::Stdout) => listener.on_text(line, OutputStream::Stdout),
+            Self::Text(line, OutputStream::Stderr) => listener.on_stderr(line),
+            Self::ParseError(line, error) => listener.on_parse_error(line, error),
+            Self::Exit(_) => {}
+        }
+    }
+}
+
+type TaskFuture = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+
+/// `TaskExecutor::spawn` 返回的事件流
+///
+/// 任务随着流被轮询而推进，调用方处理事件的速度就是读取子进程输出的速度。
+/// 不再轮询时子进程的输出管道写满后会阻塞，丢弃流会终止子进程
+pub struct TaskStream {
+    events: EventQueue,
+    task: Option<TaskFuture>,
+}
+
+impl TaskStream {
+    pub(crate) fn new<F>(events: EventQueue, task: F) -> Self
+    where
+        F: Future<Output = Result<()>> + Send + 'static,
+    {
+        Self {
+            events,
+            task: Some(Box::pin(task)),
+        }
+    }
+
+    /// 等待下一个事件，`Exit` 之后返回 `None`
+    pub async fn next(&mut self) -> Option<TaskEvent> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl Stream for TaskStream {
+    type Item = TaskEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<TaskEvent>> {
+        loop {
+            if let Some(event) = self.events.pop() {
+                return Poll::Ready(Some(event));
+            }
+            let Some(task) = self.task.as_mut() else {
+                return Poll::Ready(None);
+            };
+            match task.as_mut().poll(cx) {
+                // 最后一次轮询可能还产生了事件，Exit 排在它们之后
+                Poll::Ready(result) => {
+                    self.task = None;
+                    self.events.push(TaskEvent::Exit(result));
+                }
+                Poll::Pending if self.events.is_empty() => return Poll::Pending,
+                Poll::Pending => {}
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for TaskStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TaskStream")
+            .field("finished", &self.task.is_none())
+            .finish()
+    }
+}
+
+/// 把监听器回调转换成事件，供 `TaskStream` 取出
+#[derive(Clone, Default)]
+pub(crate) struct EventQueue(Arc<Mutex<VecDeque<TaskEvent>>>);
+
+impl EventQueue {
+    fn push(&self, event: TaskEvent) {
+        self.0
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push_back(event);
+    }
+
+    fn pop(&self) -> Option<TaskEvent> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).pop_front()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).is_empty()
+    }
+}
+
+impl MessageListener for EventQueue {
+    fn dispatch(&mut self, message: Message) {
+        match message {
+            Message::NeedsInput(request) => {
+                let responder = InputResponder::new(request.id, None);
+                self.on_needs_input(request, responder)
+            }
+            message => self.push(TaskEvent::Message(message)),
+        }
+    }
+    fn on_progress(&mut self, progress: ProgressMessage) {
+        self.push(TaskEvent::Message(Message::Progress(progress)));
+    }
+    fn on_error(&mut self, error: ErrorMessage) {
+        self.push(TaskEvent::Message(Message::Error(error)));
+    }
+    fn on_result(&mut self, result: ResultMessage) {
+        self.push(TaskEvent::Message(Message::Result(result)));
+    }
+    fn on_output(&mut self, output: OutputMessage) {
+        self.push(TaskEvent::Message(Message::Output(output)));
+    }
+    fn on_hello(&mut self, hello: HelloMessage) {
+        self.push(TaskEvent::Message(Message::Hello(hello)));
+    }
+    fn on_response(&mut self, response: ResponseMessage) {
+        self.push(TaskEvent::Message(Message::Response(response)));
+    }
+    fn on_retry(&mut self, retry: RetryMessage) {
+        self.push(TaskEvent::Message(Message::Retry(retry)));
+    }
+    fn on_needs_input(&mut self, request: NeedsInputMessage, responder: InputResponder) {
+        self.push(TaskEvent::NeedsInput(request, responder));
+    }
+    fn on_stderr(&mut self, line: String) {
+        self.push(TaskEvent::Text(line, OutputStream::Stderr));
+    }
+    fn on_text(&mut self, line: String, stream: OutputStream) {
+        self.push(TaskEvent::Text(line, stream));
+    }
+    fn on_parse_error(&mut self, line: String, error: serde_json::Error) {
+        self.push(TaskEvent::ParseError(line, error));
+    }
+}
@@ -0,0 +1,48 @@
+//! 单元测试共用的监听器
+
+use crate::ipc::{ErrorMessage, ProgressMessage, ResultMessage};
+use crate::listener::MessageListener;
+use std::sync::{Arc, Mutex};
+
+/// 忽略所有消息
+pub(crate) struct NullListener;
+
+impl MessageListener for NullListener {
+    fn on_progress(&mut self, _progress: ProgressMessage) {}
+    fn on_error(&mut self, _error: ErrorMessage) {}
+    fn on_result(&mut self, _result: ResultMessage) {}
+}
+
+/// 把收到的回调按顺序记录为字符串，克隆后共享同一份记录
+#[derive(Default, Clone)]
+pub(crate) struct RecordingListener {
+    events: Arc<Mutex<Vec<String>>>,
+}
+
+impl RecordingListener {
+    pub(crate) fn events(&self) -> Vec<String> {
+        self.events.lock().unwrap().clone()
+    }
+
+    fn record(&self, event: String) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+impl MessageListener for RecordingListener {
+    fn on_progress(&mut self, progress: ProgressMessage) {
+        self.record(format!("progress {}", progress.done));
+    }
+    fn on_error(&mut self, error: ErrorMessage) {
+        self.record(format!("error {}", error.error_code));
+    }
+    fn on_result(&mut self, _result: ResultMessage) {
+        self.record("result".into());
+    }
+    fn on_parse_error(&mut self, line: String, error: serde_json::Error) {
+        self.record(format!("parse error {line}: {error}"));
+    }
+    fn on_complete(&mut self, error_code: i32) {
+        self.record(format!("complete {error_code}"));
+    }
+}
@@ -0,0 +1,219 @@
+use crate::control::InputResponder;
+use crate::ipc::{
+    ErrorMessage, HelloMessage, NeedsInputMessage, OutputMessage, ProgressMessage, ResponseMessage,
+    ResultMessage, RetryMessage,
+};
+use crate::listener::{MessageListener, OutputStream};
+use std::time::{Duration, Instant};
+
+/// 默认最多每 100ms 转发一次进度
+const DEFAULT_MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 合并过于频繁的进度消息后再交给内层监听器
+///
+/// 满足以下任一条件的进度会立即转发，其余只保留最新的一条：
+/// - 第一条进度，或阶段、indeterminate 发生变化
+/// - 距上次转发超过 `min_interval`
+/// - 完成比例变化超过 `min_delta`（需要开启）
+///
+/// 其他消息到来、任务结束或监听器释放时，先补发保留的最新进度，保证最后一条进度不会丢失。
+/// 普通文本行不会触发补发
+pub struct ThrottledListener<L: MessageListener> {
+    inner: L,
+    min_interval: Duration,
+    min_delta: Option<f64>,
+    last_sent: Option<(Instant, ProgressMessage)>,
+    pending: Option<ProgressMessage>,
+    suppressed: u64,
+}
+
+impl<L: MessageListener> ThrottledListener<L> {
+    pub fn new(inner: L) -> Self {
+        Self {
+            inner,
+            min_interval: DEFAULT_MIN_INTERVAL,
+            min_delta: None,
+            last_sent: None,
+            pending: None,
+            suppressed: 0,
+        }
+    }
+
+    /// 两次转发之间的最短间隔
+    pub fn with_min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = min_interval;
+        self
+    }
+
+    /// 每秒最多转发多少次进度，等价于 `with_min_interval(1s / rate)`
+    pub fn with_max_rate(self, per_second: f64) -> Self {
+        self.with_min_interval(Duration::from_secs_f64(1.0 / per_second.max(f64::EPSILON)))
+    }
+
+    /// 完成比例（`[0, 1]`）变化超过 `delta` 时不受间隔限制立即转发
+    pub fn with_min_delta(mut self, delta: f64) -> Self {
+        self.min_delta = Some(delta);
+        self
+    }
+
+    pub fn inner(&self) -> &L {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut L {
+        &mut self.inner
+    }
+
+    /// 被合并掉、没有转发的进度数量
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed
+    }
+
+    /// 立即转发保留的最新进度
+    pub fn flush(&mut self) {
+        if let Some(progress) = self.pending.take() {
+            self.send(progress, Instant::now());
+        }
+    }
+
+    fn on_progress_at(&mut self, progress: ProgressMessage, now: Instant) {
+        let replaced = if self.should_send(&progress, now) {
+            let replaced = self.pending.take();
+            self.send(progress, now);
+            replaced
+        } else {
+            self.pending.replace(progress)
+        };
+        if replaced.is_some() {
+            self.suppressed += 1;
+        }
+    }
+
+    fn should_send(&self, progress: &ProgressMessage, now: Instant) -> bool {
+        let Some((sent_at, sent)) = &self.last_sent else {
+            return true;
+        };
+        if sent.stage != progress.stage || sent.indeterminate != progress.indeterminate {
+            return true;
+        }
+        if now.duration_since(*sent_at) >= self.min_interval {
+            return true;
+        }
+        match (self.min_delta, sent.fraction(), progress.fraction()) {
+            (Some(delta), Some(from), Some(to)) => (to - from).abs() >= delta,
+            _ => false,
+        }
+    }
+
+    fn send(&mut self, progress: ProgressMessage, now: Instant) {
+        self.last_sent = Some((now, progress.clone()));
+        self.inner.on_progress(progress);
+    }
+}
+
+impl<L: MessageListener> MessageListener for ThrottledListener<L> {
+    fn on_progress(&mut self, progress: ProgressMessage) {
+        self.on_progress_at(progress, Instant::now());
+    }
+    fn on_error(&mut self, error: ErrorMessage) {
+        self.flush();
+        self.inner.on_error(error);
+    }
+    fn on_result(&mut self, result: ResultMessage) {
+        self.flush();
+        self.inner.on_result(result);
+    }
+    fn on_output(&mut self, output: OutputMessage) {
+        self.flush();
+        self.inner.on_output(output);
+    }
+    fn on_hello(&mut self, hello: HelloMessage) {
+        self.flush();
+        self.inner.on_hello(hello);
+    }
+    fn on_response(&mut self, response: ResponseMessage) {
+        self.flush();
+        self.inner.on_response(response);
+    }
+    fn on_retry(&mut self, retry: RetryMessage) {
+        self.flush();
+        self.inner.on_retry(retry);
+    }
+    fn on_needs_input(&mut self, request: NeedsInputMessage, responder: InputResponder) {
+        self.flush();
+        self.inner.on_needs_input(request, responder);
+    }
+    fn on_stderr(&mut self, line: String) {
+        self.inner.on_stderr(line);
+    }
+    fn on_text(&mut self, line: String, stream: OutputStream) {
+        self.inner.on_text(line, stream);
+    }
+    fn on_parse_error(&mut self, line: String, error: serde_json::Error) {
+        self.inner.on_parse_error(line, error);
+    }
+    fn on_complete(&mut self, error_code: i32) {
+        self.flush();
+        self.inner.on_complete(error_code);
+    }
+}
+
+impl<L: MessageListener> Drop for ThrottledListener<L> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::RecordingListener;
+
+    #[test]
+    fn test_throttle_by_interval() {
+        let recording = RecordingListener::default();
+        let mut listener =
+            ThrottledListener::new(recording.clone()).with_min_interval(Duration::from_secs(1));
+        let start = Instant::now();
+        for done in 0..1000 {
+            listener.on_progress_at(
+                ProgressMessage::new(done, 1000),
+                start + Duration::from_millis(done),
+            );
+        }
+        listener.on_result(ResultMessage::new(1, 2));
+        assert_eq!(listener.suppressed(), 998);
+
+        assert_eq!(
+            recording.events(),
+            vec!["progress 0", "progress 999", "result"]
+        );
+    }
+
+    #[test]
+    fn test_throttle_by_delta() {
+        let recording = RecordingListener::default();
+        let mut listener = ThrottledListener::new(recording.clone())
+            .with_min_interval(Duration::from_secs(60))
+            .with_min_delta(0.25);
+        let now = Instant::now();
+        for done in 0..=100 {
+            listener.on_progress_at(ProgressMessage::new(done, 100), now);
+        }
+        // 阶段变化时立即转发
+        listener.on_progress_at(ProgressMessage::new(0, 10).with_stage("排版", 2, 2), now);
+        drop(listener);
+
+        assert_eq!(
+            recording.events(),
+            vec![
+                "progress 0",
+                "progress 25",
+                "progress 50",
+                "progress 75",
+                "progress 100",
+                "progress 0"
+            ]
+        );
+    }
+}
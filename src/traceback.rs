@@ -0,0 +1,224 @@
+use crate::error::PyRunnerError;
+
+const TRACEBACK_HEADER: &str = "Traceback (most recent call last):";
+
+/// Python 调用栈中的一帧
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PythonFrame {
+    pub file: String,
+    pub line: u32,
+    pub function: String,
+}
+
+/// 从 stderr 中解析出的 Python 异常
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PythonTraceback {
+    pub exc_type: String,
+    pub message: String,
+    pub frames: Vec<PythonFrame>,
+}
+
+impl PythonTraceback {
+    /// 导入失败映射为 `PythonModuleImportFailed`，其余映射为 `PythonError`
+    pub fn into_error(self) -> PyRunnerError {
+        match self.exc_type.as_str() {
+            "ModuleNotFoundError" | "ImportError" => PyRunnerError::PythonModuleImportFailed {
+                module: import_failed_module(&self.message).unwrap_or(self.message),
+            },
+            _ => PyRunnerError::PythonError {
+                exc_type: self.exc_type,
+                message: self.message,
+                frames: self.frames,
+            },
+        }
+    }
+}
+
+/// 逐行接收 stderr，识别其中的 `Traceback (most recent call last):` 块
+///
+/// 只保留紧挨着 stderr 末尾的异常（例如异常链的最后一个）。异常之后又出现其他输出时丢弃它，
+/// 脚本自行捕获并打印的异常不会被当作退出原因
+#[derive(Debug, Default)]
+pub struct TracebackParser {
+    in_traceback: bool,
+    frames: Vec<PythonFrame>,
+    last: Option<PythonTraceback>,
+}
+
+impl TracebackParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, line: &str) {
+        let line = line.trim_end();
+        if line == TRACEBACK_HEADER {
+            self.in_traceback = true;
+            self.frames.clear();
+            return;
+        }
+        if line.is_empty() {
+            return;
+        }
+        if !self.in_traceback {
+            self.last = None;
+            return;
+        }
+
+        if let Some(frame) = line.trim_start().strip_prefix("File ") {
+            if let Some(frame) = parse_frame(frame) {
+                self.frames.push(frame);
+            }
+        } else if !line.starts_with(char::is_whitespace) {
+            // 栈帧之后第一个顶格的行就是异常类型和消息
+            let (exc_type, message) = line.split_once(": ").unwrap_or((line, ""));
+            self.last = Some(PythonTraceback {
+                exc_type: exc_type.to_string(),
+                message: message.to_string(),
+                frames: std::mem::take(&mut self.frames),
+            });
+            self.in_traceback = false;
+        }
+    }
+
+    pub fn traceback(&self) -> Option<&PythonTraceback> {
+        self.last.as_ref()
+    }
+
+    pub fn take(&mut self) -> Option<PythonTraceback> {
+        self.last.take()
+    }
+}
+
+/// 解析 `"path/to/file.py", line 12, in func`
+fn parse_frame(frame: &str) -> Option<PythonFrame> {
+    let rest = frame.strip_prefix('"')?;
+    let (file, rest) = rest.split_once("\", line ")?;
+    let (line, function) = match rest.split_once(", in ") {
+        Some((line, function)) => (line, function),
+        None => (rest, ""),
+    };
+    Some(PythonFrame {
+        file: file.to_string(),
+        line: line.trim().parse().ok()?,
+        function: function.to_string(),
+    })
+}
+
+/// 从导入错误消息中提取模块名：
+/// - `No module named 'foo.bar'`
+/// - `cannot import name 'x' from 'foo' (/path/foo.py)`
+fn import_failed_module(message: &str) -> Option<String> {
+    let quoted = match message.split_once(" from ") {
+        Some((_, from)) => from,
+        None => message.strip_prefix("No module named ")?,
+    };
+    let quoted = quoted.strip_prefix('\'')?;
+    let (module, _) = quoted.split_once('\'')?;
+    Some(module.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(stderr: &str) -> Option<PythonTraceback> {
+        let mut parser = TracebackParser::new();
+        stderr.lines().for_each(|line| parser.feed(line));
+        parser.take()
+    }
+
+    #[test]
+    fn test_parse_traceback() {
+        let traceback = parse(
+            r#"some warning
+Traceback (most recent call last):
+  File "/app/convert.py", line 42, in <module>
+    main()
+  File "/app/convert.py", line 30, in main
+    raise ValueError("bad page: 3")
+    ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^
+ValueError: bad page: 3
+"#,
+        )
+        .unwrap();
+        assert_eq!(traceback.exc_type, "ValueError");
+        assert_eq!(traceback.message, "bad page: 3");
+        assert_eq!(
+            traceback.frames,
+            vec![
+                PythonFrame {
+                    file: "/app/convert.py".into(),
+                    line: 42,
+                    function: "<module>".into(),
+                },
+                PythonFrame {
+                    file: "/app/convert.py".into(),
+                    line: 30,
+                    function: "main".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_chained_traceback() {
+        let traceback = parse(
+            r#"Traceback (most recent call last):
+  File "a.py", line 2, in <module>
+KeyError: 'x'
+
+During handling of the above exception, another exception occurred:
+
+Traceback (most recent call last):
+  File "a.py", line 4, in <module>
+KeyboardInterrupt
+"#,
+        )
+        .unwrap();
+        assert_eq!(traceback.exc_type, "KeyboardInterrupt");
+        assert_eq!(traceback.message, "");
+        assert_eq!(traceback.frames.len(), 1);
+        assert_eq!(traceback.frames[0].line, 4);
+    }
+
+    #[test]
+    fn test_handled_traceback_discarded() {
+        let stderr = r#"Traceback (most recent call last):
+  File "a.py", line 3, in <module>
+ValueError: retrying
+retry succeeded
+"#;
+        assert!(parse(stderr).is_none());
+    }
+
+    #[test]
+    fn test_no_traceback() {
+        assert!(parse("进度: 10.0%\nWarning: something\n").is_none());
+    }
+
+    #[test]
+    fn test_import_error() {
+        let error = PythonTraceback {
+            exc_type: "ModuleNotFoundError".into(),
+            message: "No module named 'fitz'".into(),
+            frames: vec![],
+        }
+        .into_error();
+        assert!(matches!(
+            error,
+            PyRunnerError::PythonModuleImportFailed { module } if module == "fitz"
+        ));
+
+        let error = PythonTraceback {
+            exc_type: "ImportError".into(),
+            message: "cannot import name 'open' from 'fitz' (/x/fitz/__init__.py)".into(),
+            frames: vec![],
+        }
+        .into_error();
+        assert!(matches!(
+            error,
+            PyRunnerError::PythonModuleImportFailed { module } if module == "fitz"
+        ));
+    }
+}
@@ -0,0 +1,47 @@
+//! 集成测试共用的监听器
+
+use pr::ipc::{
+    ErrorMessage, HelloMessage, OutputMessage, ProgressMessage, ResponseMessage, ResultMessage,
+};
+use pr::listener::{MessageListener, OutputStream};
+
+/// 按类型记录收到的消息，stdout 上的普通文本记入 `text`，解析失败直接 panic
+#[derive(Default)]
+pub struct RecordingListener {
+    pub progress: Vec<ProgressMessage>,
+    pub errors: Vec<ErrorMessage>,
+    pub results: Vec<ResultMessage>,
+    pub outputs: Vec<OutputMessage>,
+    pub hellos: Vec<HelloMessage>,
+    pub responses: Vec<ResponseMessage>,
+    pub text: Vec<String>,
+}
+
+impl MessageListener for RecordingListener {
+    fn on_progress(&mut self, progress: ProgressMessage) {
+        self.progress.push(progress);
+    }
+    fn on_error(&mut self, error: ErrorMessage) {
+        self.errors.push(error);
+    }
+    fn on_result(&mut self, result: ResultMessage) {
+        self.results.push(result);
+    }
+    fn on_output(&mut self, output: OutputMessage) {
+        self.outputs.push(output);
+    }
+    fn on_hello(&mut self, hello: HelloMessage) {
+        self.hellos.push(hello);
+    }
+    fn on_response(&mut self, response: ResponseMessage) {
+        self.responses.push(response);
+    }
+    fn on_text(&mut self, line: String, stream: OutputStream) {
+        if stream == OutputStream::Stdout {
+            self.text.push(line);
+        }
+    }
+    fn on_parse_error(&mut self, line: String, error: serde_json::Error) {
+        panic!("unexpected parse error: {error}, line: {line}");
+    }
+}
@@ -0,0 +1,272 @@
+mod common;
+
+use common::RecordingListener;
+use pr::control::InputResponder;
+use pr::error::PyRunnerError;
+use pr::executor::{DEFAULT_MESSAGE_FD, DEFAULT_MESSAGE_PREFIX, TaskExecutor};
+use pr::ipc::{
+    ErrorMessage, HelloMessage, InputKind, NeedsInputMessage, OutputMessage, PROTOCOL_VERSION,
+    ProgressMessage, ProgressUnit, ResultMessage,
+};
+use pr::listener::MessageListener;
+use pr::python_client::install_python_client;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+fn client_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("python_client")
+}
+
+fn script_executor(script: &str, args: &[&str]) -> TaskExecutor {
+    let mut argv = vec![format!("tests/scripts/{script}")];
+    argv.extend(args.iter().map(|arg| arg.to_string()));
+    TaskExecutor::new("python".into(), argv)
+        .with_python_client(client_dir())
+        .with_require_hello(true)
+}
+
+#[tokio::test]
+async fn test_client_over_stdout() {
+    let executor = script_executor("client_progress.py", &["4"]);
+
+    let mut listener = RecordingListener::default();
+    executor.execute(&mut listener).await.unwrap();
+    assert_eq!(
+        listener.hellos,
+        vec![HelloMessage::new(PROTOCOL_VERSION).with_script("client_progress.py")]
+    );
+    assert_eq!(listener.progress.len(), 4);
+    assert_eq!(listener.progress[3], ProgressMessage::new(4, 4));
+    assert_eq!(listener.results, vec![ResultMessage::new(4, 400)]);
+    assert_eq!(listener.text, vec!["开始处理"]);
+    assert!(listener.errors.is_empty());
+}
+
+#[tokio::test]
+async fn test_client_over_message_fd_with_prefix() {
+    let executor = script_executor("client_progress.py", &["3"])
+        .with_message_fd(DEFAULT_MESSAGE_FD)
+        .with_message_prefix(DEFAULT_MESSAGE_PREFIX);
+
+    let mut listener = RecordingListener::default();
+    executor.execute(&mut listener).await.unwrap();
+    assert_eq!(listener.progress.len(), 3);
+    assert_eq!(listener.results, vec![ResultMessage::new(3, 300)]);
+    assert_eq!(listener.text, vec!["开始处理"]);
+}
+
+#[tokio::test]
+async fn test_client_reports_extended_progress() {
+    let executor = script_executor("client_stages.py", &[]);
+
+    let mut listener = RecordingListener::default();
+    executor.execute(&mut listener).await.unwrap();
+    assert_eq!(
+        listener.progress,
+        vec![
+            ProgressMessage::new(0, 0)
+                .with_stage("解析", 1, 2)
+                .with_indeterminate(true),
+            ProgressMessage::new(3, 10)
+                .with_stage("排版", 2, 2)
+                .with_message("第 3 页")
+                .with_unit(ProgressUnit::Pages),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_client_reports_output() {
+    let executor = script_executor("client_output.py", &[]);
+
+    let mut listener = RecordingListener::default();
+    executor.execute(&mut listener).await.unwrap();
+    assert_eq!(
+        listener.outputs,
+        vec![OutputMessage::new(
+            serde_json::json!({"text": "你好", "lines": 2}),
+            vec!["out/page-1.png".into()],
+        )]
+    );
+    assert!(listener.results.is_empty());
+}
+
+#[tokio::test]
+async fn test_client_reports_uncaught_exception() {
+    let executor = script_executor("client_error.py", &[]);
+
+    let mut listener = RecordingListener::default();
+    let result = executor.execute(&mut listener).await;
+    assert_eq!(listener.progress, vec![ProgressMessage::new(1, 3)]);
+    assert_eq!(
+        listener.errors,
+        vec![ErrorMessage::new(2001, "ValueError: bad page: 2".into())]
+    );
+    match result {
+        Err(PyRunnerError::PythonError {
+            exc_type, frames, ..
+        }) => {
+            assert_eq!(exc_type, "ValueError");
+            assert_eq!(frames.last().unwrap().function, "convert");
+        }
+        other => panic!("unexpected result: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_client_control_channel() {
+    let executor = script_executor("client_control.py", &[]).with_control_channel();
+    let control = executor.control_handle().unwrap();
+
+    let mut listener = RecordingListener::default();
+    let driver = async {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        control.pause();
+        let response = control.request(serde_json::json!("ping")).await.unwrap();
+        control.resume();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        control.cancel();
+        response
+    };
+    let (result, response) = tokio::join!(executor.execute(&mut listener), driver);
+
+    assert!(matches!(result, Err(PyRunnerError::TaskCancelled { .. })));
+    assert_eq!(response.payload, serde_json::json!({"echo": "ping"}));
+    assert_eq!(response.error, None);
+    assert_eq!(listener.responses, vec![response]);
+    assert!(listener.hellos[0].supports("control"));
+    assert!(!listener.progress.is_empty());
+    assert!(listener.errors.is_empty());
+}
+
+#[tokio::test]
+async fn test_client_slow_request_handler() {
+    let executor = script_executor("client_slow_request.py", &[]).with_control_channel();
+    let control = executor.control_handle().unwrap();
+
+    let mut listener = RecordingListener::default();
+    let driver = async {
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        // 处理函数在专门的线程中执行，应答之前取消命令照样能送达
+        let response = control.request(serde_json::json!("ping"));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        control.cancel();
+        response.await
+    };
+    let start = std::time::Instant::now();
+    let (result, response) = tokio::join!(executor.execute(&mut listener), driver);
+
+    assert!(matches!(result, Err(PyRunnerError::TaskCancelled { .. })));
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert!(matches!(response, Err(PyRunnerError::ChannelClosed)));
+}
+
+#[tokio::test]
+async fn test_client_ignores_malformed_command() {
+    install_python_client(&client_dir()).unwrap();
+    let mut child = tokio::process::Command::new("python")
+        .arg("tests/scripts/client_control.py")
+        .env("PYTHONPATH", client_dir())
+        .env("PYRUNNER_CONTROL", "stdin")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(b"{broken\n\"Cancel\"\n").await.unwrap();
+
+    // 控制线程跳过无法解析的一行，之后的取消命令仍然生效
+    let output = tokio::time::timeout(Duration::from_secs(10), child.wait_with_output())
+        .await
+        .expect("Cancel after a malformed command was ignored")
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("忽略无法解析的控制命令"), "{stderr}");
+    assert!(stderr.contains("Cancelled"), "{stderr}");
+}
+
+/// 按顺序应答密码请求，`None` 表示放弃输入
+struct PasswordListener {
+    answers: Vec<Option<&'static str>>,
+    requests: Vec<NeedsInputMessage>,
+    errors: Vec<ErrorMessage>,
+    outputs: Vec<OutputMessage>,
+}
+
+impl PasswordListener {
+    fn new(answers: Vec<Option<&'static str>>) -> Self {
+        Self {
+            answers,
+            requests: Vec::new(),
+            errors: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+}
+
+impl MessageListener for PasswordListener {
+    fn on_progress(&mut self, _progress: ProgressMessage) {}
+    fn on_error(&mut self, error: ErrorMessage) {
+        self.errors.push(error);
+    }
+    fn on_result(&mut self, _result: ResultMessage) {}
+    fn on_output(&mut self, output: OutputMessage) {
+        self.outputs.push(output);
+    }
+    fn on_needs_input(&mut self, request: NeedsInputMessage, responder: InputResponder) {
+        let answer = self.answers[self.requests.len()];
+        self.requests.push(request);
+        // 应答器可以转交给其他线程，模拟 UI 异步输入
+        std::thread::spawn(move || match answer {
+            Some(password) => responder.reply(password),
+            None => responder.decline(),
+        });
+    }
+}
+
+#[tokio::test]
+async fn test_client_password_round_trip() {
+    let executor = script_executor("client_password.py", &[]).with_control_channel();
+
+    let mut listener = PasswordListener::new(vec![Some("wrong"), Some("secret")]);
+    executor.execute(&mut listener).await.unwrap();
+    assert_eq!(listener.requests.len(), 2);
+    assert_eq!(listener.requests[0].kind, InputKind::Password);
+    assert_eq!(listener.requests[1].attempt, 2);
+    assert_eq!(
+        listener.outputs[0].value,
+        serde_json::json!({"password": "secret"})
+    );
+}
+
+#[tokio::test]
+async fn test_client_password_incorrect() {
+    let executor = script_executor("client_password.py", &[])
+        .with_control_channel()
+        .with_input_attempts(2);
+
+    let mut listener = PasswordListener::new(vec![Some("a"), Some("b")]);
+    let result = executor.execute(&mut listener).await;
+    match result {
+        Err(error @ PyRunnerError::PasswordIncorrect { attempts: 2 }) => {
+            assert_eq!(error.error_code(), 4004);
+        }
+        other => panic!("unexpected result: {other:?}"),
+    }
+    assert_eq!(listener.errors[0].error_code, 4004);
+}
+
+#[tokio::test]
+async fn test_client_password_declined() {
+    let executor = script_executor("client_password.py", &[]).with_control_channel();
+
+    let mut listener = PasswordListener::new(vec![None]);
+    let result = executor.execute(&mut listener).await;
+    assert!(matches!(result, Err(PyRunnerError::TaskCancelled { .. })));
+    assert!(listener.errors.is_empty());
+}
@@ -0,0 +1,10 @@
+import pyrunner
+
+
+def convert(page):
+    raise ValueError("bad page: %d" % page)
+
+
+with pyrunner.task():
+    pyrunner.report_progress(1, 3)
+    convert(2)
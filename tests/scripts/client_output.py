@@ -0,0 +1,4 @@
+import pyrunner
+
+with pyrunner.task():
+    pyrunner.report_output({"text": "你好", "lines": 2}, artifacts=["out/page-1.png"])
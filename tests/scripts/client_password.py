@@ -0,0 +1,5 @@
+import pyrunner
+
+with pyrunner.task():
+    password = pyrunner.request_password("请输入密码", lambda p: p == "secret")
+    pyrunner.report_output({"password": password})
@@ -0,0 +1,10 @@
+import sys
+
+import pyrunner
+
+with pyrunner.task():
+    total = int(sys.argv[1]) if len(sys.argv) > 1 else 5
+    print("开始处理")
+    for i in range(1, total + 1):
+        pyrunner.report_progress(i, total)
+    pyrunner.report_result(total, total * 100)
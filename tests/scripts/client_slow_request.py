@@ -0,0 +1,18 @@
+import time
+
+import pyrunner
+
+
+@pyrunner.on_request
+def handle(payload):
+    time.sleep(30)
+    return {"echo": payload}
+
+
+with pyrunner.task():
+    done = 0
+    while True:
+        pyrunner.checkpoint()
+        done += 1
+        pyrunner.report_progress(done, 0, indeterminate=True)
+        time.sleep(0.05)
@@ -0,0 +1,7 @@
+import pyrunner
+
+with pyrunner.task():
+    pyrunner.report_progress(0, stage="解析", stage_index=1, stage_count=2, indeterminate=True)
+    pyrunner.report_progress(
+        3, 10, stage="排版", stage_index=2, stage_count=2, message="第 3 页", unit="pages"
+    )